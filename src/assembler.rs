@@ -0,0 +1,672 @@
+// A two-pass assembler for Intel 8080 source.
+//
+// Each line is `[label:] [mnemonic [operand, ...]] [; comment]`, or
+// `name EQU expression`. Besides the instruction set it understands the ORG,
+// DB, DW, DS, EQU and END directives. Operands are expressions built from
+// numbers (decimal, or with an H, O/Q, B or D suffix), character constants
+// ('A', 'AB'), symbols, `$` (the address of the current line), parentheses,
+// the arithmetic operators + - * / MOD SHL SHR, the logical operators NOT AND
+// OR XOR and HIGH/LOW. Mnemonics and symbols are case-insensitive.
+//
+// The first pass only works out where everything goes, so symbols may be used
+// before they are defined, except in ORG, DS and EQU, whose operands decide
+// addresses themselves.
+//
+// Instructions are encoded by looking them up in the decoder, so the two can
+// never disagree about what an opcode means.
+
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+
+use crate::cpu::decoder::{decode, Condition, Instruction};
+use crate::cpu::{Reg8, RegPair};
+
+// The assembled program: `image` is to be loaded at `origin` (the lowest
+// address anything was assembled to); gaps left by ORG and DS are zero.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub origin: u16,
+    pub image: Vec<u8>,
+    pub symbols: BTreeMap<String, u16>
+}
+
+// Why a source line could not be assembled. Lines are numbered from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for AsmError {}
+
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    let mut asm = Assembler {
+        symbols: BTreeMap::new(),
+        pc: 0,
+        final_pass: false,
+        memory: vec![0; 0x10000],
+        used: None
+    };
+
+    for &final_pass in &[false, true] {
+        asm.pc = 0;
+        asm.final_pass = final_pass;
+
+        for (n, text) in source.lines().enumerate() {
+            let end = asm.line(text).map_err(|message| AsmError { line: n + 1, message })?;
+            if end {
+                break;
+            }
+        }
+    }
+
+    let (origin, image) = match asm.used {
+        Some((low, high)) => (low as u16, asm.memory[low..high].to_vec()),
+        None => (0, Vec::new())
+    };
+
+    Ok(Program {
+        origin,
+        image,
+        symbols: asm.symbols.into_iter().map(|(name, value)| (name, value as u16)).collect()
+    })
+}
+
+const IMPLIED: [(&str, Instruction); 17] = [
+    ("NOP", Instruction::Nop), ("RLC", Instruction::Rlc), ("RRC", Instruction::Rrc),
+    ("RAL", Instruction::Ral), ("RAR", Instruction::Rar), ("DAA", Instruction::Daa),
+    ("CMA", Instruction::Cma), ("STC", Instruction::Stc), ("CMC", Instruction::Cmc),
+    ("HLT", Instruction::Hlt), ("RET", Instruction::Ret), ("PCHL", Instruction::Pchl),
+    ("XTHL", Instruction::Xthl), ("SPHL", Instruction::Sphl), ("XCHG", Instruction::Xchg),
+    ("EI", Instruction::Ei), ("DI", Instruction::Di)
+];
+
+type RegisterOp = fn(Reg8) -> Instruction;
+type PairOp = fn(RegPair) -> Instruction;
+type ByteOp = fn(u8) -> Instruction;
+type WordOp = fn(u16) -> Instruction;
+
+const REGISTER: [(&str, RegisterOp); 10] = [
+    ("INR", Instruction::Inr), ("DCR", Instruction::Dcr), ("ADD", Instruction::Add),
+    ("ADC", Instruction::Adc), ("SUB", Instruction::Sub), ("SBB", Instruction::Sbb),
+    ("ANA", Instruction::Ana), ("XRA", Instruction::Xra), ("ORA", Instruction::Ora),
+    ("CMP", Instruction::Cmp)
+];
+
+const PAIR: [(&str, PairOp); 7] = [
+    ("STAX", Instruction::Stax), ("LDAX", Instruction::Ldax), ("INX", Instruction::Inx),
+    ("DCX", Instruction::Dcx), ("DAD", Instruction::Dad), ("PUSH", Instruction::Push),
+    ("POP", Instruction::Pop)
+];
+
+const IMMEDIATE: [(&str, ByteOp); 10] = [
+    ("ADI", Instruction::Adi), ("ACI", Instruction::Aci), ("SUI", Instruction::Sui),
+    ("SBI", Instruction::Sbi), ("ANI", Instruction::Ani), ("XRI", Instruction::Xri),
+    ("ORI", Instruction::Ori), ("CPI", Instruction::Cpi), ("IN", Instruction::In),
+    ("OUT", Instruction::Out)
+];
+
+const ADDRESS: [(&str, WordOp); 6] = [
+    ("SHLD", Instruction::Shld), ("LHLD", Instruction::Lhld), ("STA", Instruction::Sta),
+    ("LDA", Instruction::Lda), ("JMP", Instruction::Jmp), ("CALL", Instruction::Call)
+];
+
+const CONDITIONS: [(&str, Condition); 8] = [
+    ("NZ", Condition::NotZero), ("Z", Condition::Zero), ("NC", Condition::NoCarry),
+    ("C", Condition::Carry), ("PO", Condition::ParityOdd), ("PE", Condition::ParityEven),
+    ("P", Condition::Plus), ("M", Condition::Minus)
+];
+
+struct Assembler {
+    symbols: BTreeMap<String, i64>,
+    // Location counter; one past the top of memory once the last byte is
+    // used.
+    pc: u32,
+    final_pass: bool,
+    memory: Vec<u8>,
+    // Lowest address written and one past the highest.
+    used: Option<(usize, usize)>
+}
+
+impl Assembler {
+    // Assemble one source line, returning true on END.
+    fn line(&mut self, text: &str) -> Result<bool, String> {
+        let text = strip_comment(text).trim();
+
+        let (first, after) = split_word(text);
+        let (label, statement) = match after.strip_prefix(':') {
+            Some(rest) if !first.is_empty() => (Some(first), rest.trim()),
+            _ => (None, text)
+        };
+
+        let (mut mnemonic, mut operands) = split_word(statement);
+        let mut label = label;
+        if label.is_none() {
+            let (second, rest) = split_word(operands.trim());
+            if second.eq_ignore_ascii_case("EQU") {
+                label = Some(mnemonic);
+                mnemonic = second;
+                operands = rest;
+            }
+        }
+        let mnemonic = mnemonic.to_ascii_uppercase();
+        let operands = operands.trim();
+
+        if mnemonic.is_empty() && !operands.is_empty() {
+            return Err(format!("expected an instruction, found '{}'", operands));
+        }
+
+        if mnemonic == "EQU" {
+            let name = label.ok_or("EQU needs a name")?;
+            let value = self.evaluate(operands, true)?;
+            if !(-0x8000..=0xffff).contains(&value) {
+                return Err(format!("{} does not fit in a word", value));
+            }
+            self.define(name, value)?;
+            return Ok(false);
+        }
+
+        if let Some(name) = label {
+            if self.pc > 0xffff {
+                return Err(format!("{} is past the end of memory", name.to_ascii_uppercase()));
+            }
+            self.define(name, self.pc as i64)?;
+        }
+
+        match mnemonic.as_str() {
+            "" => {}
+            "END" => return Ok(true),
+            "ORG" => {
+                self.pc = self.address(operands, true)? as u32;
+            }
+            "DS" => {
+                let size = self.evaluate(operands, true)?;
+                if size < 0 || self.pc as i64 + size > 0x10000 {
+                    return Err(format!("cannot reserve {} bytes here", size));
+                }
+                self.pc += size as u32;
+            }
+            // The data is emitted in one go so that `$` means the start of the
+            // line in every operand.
+            "DB" => {
+                let mut data = Vec::new();
+                for operand in split_operands(operands) {
+                    match tokenize(operand)?.as_slice() {
+                        [Token::Text(text)] if text.len() != 1 => data.extend_from_slice(text),
+                        _ => data.push(self.byte(operand)?)
+                    }
+                }
+                self.emit(&data)?;
+            }
+            "DW" => {
+                let mut data = Vec::new();
+                for operand in split_operands(operands) {
+                    let value = self.word(operand)?;
+                    data.extend_from_slice(&[value as u8, (value >> 8) as u8]);
+                }
+                self.emit(&data)?;
+            }
+            _ => {
+                let instruction = self.instruction(&mnemonic, &split_operands(operands))?;
+                let bytes = encode(instruction)
+                    .ok_or_else(|| format!("invalid operands for {}: {}", mnemonic, operands))?;
+                self.emit(&bytes)?;
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn define(&mut self, name: &str, value: i64) -> Result<(), String> {
+        // Everything is defined on the first pass; the second would only find
+        // the same values again.
+        if self.final_pass {
+            return Ok(());
+        }
+
+        let name = name.to_ascii_uppercase();
+        if is_keyword(&name) || register(&name).is_ok() || pair(&name).is_ok() {
+            return Err(format!("{} is reserved and cannot be used as a symbol", name));
+        }
+        if self.symbols.insert(name.clone(), value).is_some() {
+            return Err(format!("{} is defined more than once", name));
+        }
+
+        Ok(())
+    }
+
+    fn emit(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let start = self.pc as usize;
+        let end = start + bytes.len();
+        if end > 0x10000 {
+            return Err(String::from("program runs past the end of memory"));
+        }
+
+        if self.final_pass && !bytes.is_empty() {
+            self.memory[start..end].copy_from_slice(bytes);
+            self.used = Some(match self.used {
+                Some((low, high)) => (low.min(start), high.max(end)),
+                None => (start, end)
+            });
+        }
+        self.pc = end as u32;
+
+        Ok(())
+    }
+
+    fn instruction(&self, mnemonic: &str, ops: &[&str]) -> Result<Instruction, String> {
+        let expect = |count: usize| {
+            if ops.len() == count {
+                Ok(())
+            } else {
+                Err(format!("{} takes {} operand{}", mnemonic, count, if count == 1 { "" } else { "s" }))
+            }
+        };
+
+        if let Some(instruction) = lookup(&IMPLIED, mnemonic) {
+            expect(0)?;
+            return Ok(instruction);
+        }
+        if let Some(make) = lookup(&REGISTER, mnemonic) {
+            expect(1)?;
+            return Ok(make(register(ops[0])?));
+        }
+        if let Some(make) = lookup(&PAIR, mnemonic) {
+            expect(1)?;
+            return Ok(make(pair(ops[0])?));
+        }
+        if let Some(make) = lookup(&IMMEDIATE, mnemonic) {
+            expect(1)?;
+            return Ok(make(self.byte(ops[0])?));
+        }
+        if let Some(make) = lookup(&ADDRESS, mnemonic) {
+            expect(1)?;
+            return Ok(make(self.word(ops[0])?));
+        }
+
+        match mnemonic {
+            "MOV" => {
+                expect(2)?;
+                Ok(Instruction::Mov(register(ops[0])?, register(ops[1])?))
+            }
+            "MVI" => {
+                expect(2)?;
+                Ok(Instruction::Mvi(register(ops[0])?, self.byte(ops[1])?))
+            }
+            "LXI" => {
+                expect(2)?;
+                Ok(Instruction::Lxi(pair(ops[0])?, self.word(ops[1])?))
+            }
+            "RST" => {
+                expect(1)?;
+                match self.evaluate(ops[0], false)? {
+                    n @ 0..=7 => Ok(Instruction::Rst(n as u8)),
+                    n => Err(format!("RST {} is not one of RST 0 to RST 7", n))
+                }
+            }
+            _ => {
+                let unknown = || format!("unknown instruction {}", mnemonic);
+                let (kind, suffix) = mnemonic.split_at(1);
+                let condition = lookup(&CONDITIONS, suffix).ok_or_else(unknown)?;
+
+                match kind {
+                    "J" => {
+                        expect(1)?;
+                        Ok(Instruction::Jcc(condition, self.word(ops[0])?))
+                    }
+                    "C" => {
+                        expect(1)?;
+                        Ok(Instruction::Ccc(condition, self.word(ops[0])?))
+                    }
+                    "R" => {
+                        expect(0)?;
+                        Ok(Instruction::Rcc(condition))
+                    }
+                    _ => Err(unknown())
+                }
+            }
+        }
+    }
+
+    // Evaluate an operand. Until the final pass, symbols that are not yet
+    // defined read as zero unless `strict` is set.
+    fn evaluate(&self, text: &str, strict: bool) -> Result<i64, String> {
+        let tokens = tokenize(text)?;
+        if tokens.is_empty() {
+            return Err(String::from("missing operand"));
+        }
+
+        let mut parser = Parser { tokens, pos: 0, asm: self, strict: strict || self.final_pass };
+        let value = parser.or()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(value),
+            Some(_) => Err(format!("unexpected text in expression '{}'", text))
+        }
+    }
+
+    fn byte(&self, text: &str) -> Result<u8, String> {
+        match self.evaluate(text, false)? {
+            value @ -0x80..=0xff => Ok(value as u8),
+            value => Err(format!("{} does not fit in a byte", value))
+        }
+    }
+
+    fn word(&self, text: &str) -> Result<u16, String> {
+        match self.evaluate(text, false)? {
+            value @ -0x8000..=0xffff => Ok(value as u16),
+            value => Err(format!("{} does not fit in a word", value))
+        }
+    }
+
+    fn address(&self, text: &str, strict: bool) -> Result<u16, String> {
+        match self.evaluate(text, strict)? {
+            value @ 0..=0xffff => Ok(value as u16),
+            value => Err(format!("{} is not an address", value))
+        }
+    }
+}
+
+// Find the opcode the decoder maps to `instruction` and lay out its operand
+// bytes after it.
+fn encode(instruction: Instruction) -> Option<Vec<u8>> {
+    let operand = match instruction {
+        Instruction::Lxi(_, value) | Instruction::Shld(value) | Instruction::Lhld(value)
+        | Instruction::Sta(value) | Instruction::Lda(value) | Instruction::Jmp(value)
+        | Instruction::Jcc(_, value) | Instruction::Call(value) | Instruction::Ccc(_, value) => value,
+        Instruction::Mvi(_, value) | Instruction::Adi(value) | Instruction::Aci(value)
+        | Instruction::Sui(value) | Instruction::Sbi(value) | Instruction::Ani(value)
+        | Instruction::Xri(value) | Instruction::Ori(value) | Instruction::Cpi(value)
+        | Instruction::In(value) | Instruction::Out(value) => value as u16,
+        _ => 0
+    };
+
+    (0..=0xff).find_map(|opcode| {
+        let bytes = [opcode, operand as u8, (operand >> 8) as u8];
+        match decode(&bytes) {
+            (decoded, length) if decoded == instruction => Some(bytes[..length].to_vec()),
+            _ => None
+        }
+    })
+}
+
+fn lookup<T: Copy>(table: &[(&str, T)], name: &str) -> Option<T> {
+    table.iter().find(|(key, _)| *key == name).map(|&(_, value)| value)
+}
+
+fn register(text: &str) -> Result<Reg8, String> {
+    match text.trim().to_ascii_uppercase().as_str() {
+        "B" => Ok(Reg8::B),
+        "C" => Ok(Reg8::C),
+        "D" => Ok(Reg8::D),
+        "E" => Ok(Reg8::E),
+        "H" => Ok(Reg8::H),
+        "L" => Ok(Reg8::L),
+        "M" => Ok(Reg8::M),
+        "A" => Ok(Reg8::A),
+        _ => Err(format!("{} is not a register", text.trim()))
+    }
+}
+
+fn pair(text: &str) -> Result<RegPair, String> {
+    match text.trim().to_ascii_uppercase().as_str() {
+        "B" => Ok(RegPair::BC),
+        "D" => Ok(RegPair::DE),
+        "H" => Ok(RegPair::HL),
+        "SP" => Ok(RegPair::SP),
+        "PSW" => Ok(RegPair::PSW),
+        _ => Err(format!("{} is not a register pair", text.trim()))
+    }
+}
+
+fn is_keyword(name: &str) -> bool {
+    matches!(name, "MOD" | "SHL" | "SHR" | "NOT" | "AND" | "OR" | "XOR" | "HIGH" | "LOW")
+}
+
+fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '?' || c == '@' || c == '.'
+}
+
+// Split off the leading name (label, mnemonic or symbol) of `text`.
+fn split_word(text: &str) -> (&str, &str) {
+    let end = text.find(|c: char| !is_symbol_char(c)).unwrap_or(text.len());
+    text.split_at(end)
+}
+
+fn strip_comment(text: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '\'' => quoted = !quoted,
+            ';' if !quoted => return &text[..i],
+            _ => {}
+        }
+    }
+    text
+}
+
+// Split an operand list on the commas that are not inside quotes or
+// parentheses.
+fn split_operands(text: &str) -> Vec<&str> {
+    if text.is_empty() {
+        return Vec::new();
+    }
+
+    let mut operands = Vec::new();
+    let mut quoted = false;
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '\'' => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => depth -= 1,
+            ',' if !quoted && depth == 0 => {
+                operands.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    operands.push(text[start..].trim());
+
+    operands
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Symbol(String),
+    Text(Vec<u8>),
+    Here,
+    Op(char)
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '\'' {
+            // A quote inside a string is written twice.
+            let mut bytes = Vec::new();
+            i += 1;
+            loop {
+                match (chars.get(i), chars.get(i + 1)) {
+                    (None, _) => return Err(format!("unterminated string in '{}'", text)),
+                    (Some('\''), Some('\'')) => { bytes.push(b'\''); i += 2; }
+                    (Some('\''), _) => { i += 1; break; }
+                    (Some(&c), _) => { bytes.push(c as u8); i += 1; }
+                }
+            }
+            tokens.push(Token::Text(bytes));
+        } else if is_symbol_char(c) {
+            let start = i;
+            while i < chars.len() && is_symbol_char(chars[i]) {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect::<String>().to_ascii_uppercase();
+            if c.is_ascii_digit() {
+                tokens.push(Token::Number(number(&word)?));
+            } else {
+                tokens.push(Token::Symbol(word));
+            }
+        } else if c == '$' {
+            tokens.push(Token::Here);
+            i += 1;
+        } else if "+-*/()".contains(c) {
+            tokens.push(Token::Op(c));
+            i += 1;
+        } else {
+            return Err(format!("unexpected '{}' in '{}'", c, text));
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn overflow() -> String {
+    String::from("expression is too large")
+}
+
+fn number(word: &str) -> Result<i64, String> {
+    let (digits, radix) = match word.chars().last() {
+        Some('H') => (&word[..word.len() - 1], 16),
+        Some('O') | Some('Q') => (&word[..word.len() - 1], 8),
+        Some('B') => (&word[..word.len() - 1], 2),
+        Some('D') => (&word[..word.len() - 1], 10),
+        _ => (word, 10)
+    };
+
+    i64::from_str_radix(digits, radix)
+        .ok()
+        .filter(|&value| value <= 0xffff)
+        .ok_or_else(|| format!("{} is not a valid number", word))
+}
+
+// Recursive-descent evaluation of an expression, from the loosest binding
+// operators (OR, XOR) down to single values.
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    asm: &'a Assembler,
+    strict: bool
+}
+
+impl<'a> Parser<'a> {
+    fn peek_keyword(&self, keywords: &[&str]) -> Option<String> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Symbol(name)) if keywords.contains(&name.as_str()) => Some(name.clone()),
+            Some(Token::Op(op)) if keywords.contains(&op.to_string().as_str()) => Some(op.to_string()),
+            _ => None
+        }
+    }
+
+    fn or(&mut self) -> Result<i64, String> {
+        let mut value = self.and()?;
+        while let Some(op) = self.peek_keyword(&["OR", "XOR"]) {
+            self.pos += 1;
+            let rhs = self.and()?;
+            value = if op == "OR" { value | rhs } else { value ^ rhs };
+        }
+        Ok(value)
+    }
+
+    fn and(&mut self) -> Result<i64, String> {
+        let mut value = self.sum()?;
+        while self.peek_keyword(&["AND"]).is_some() {
+            self.pos += 1;
+            value &= self.sum()?;
+        }
+        Ok(value)
+    }
+
+    fn sum(&mut self) -> Result<i64, String> {
+        let mut value = self.product()?;
+        while let Some(op) = self.peek_keyword(&["+", "-"]) {
+            self.pos += 1;
+            let rhs = self.product()?;
+            let result = if op == "+" { value.checked_add(rhs) } else { value.checked_sub(rhs) };
+            value = result.ok_or_else(overflow)?;
+        }
+        Ok(value)
+    }
+
+    fn product(&mut self) -> Result<i64, String> {
+        let mut value = self.unary()?;
+        while let Some(op) = self.peek_keyword(&["*", "/", "MOD", "SHL", "SHR"]) {
+            self.pos += 1;
+            let rhs = self.unary()?;
+            value = match op.as_str() {
+                "*" => value.checked_mul(rhs).ok_or_else(overflow)?,
+                "SHL" => (value << rhs.clamp(0, 32)) & 0xffff,
+                "SHR" => (value & 0xffff) >> rhs.clamp(0, 32),
+                _ if rhs == 0 => return Err(String::from("division by zero")),
+                "/" => value.checked_div(rhs).ok_or_else(overflow)?,
+                _ => value.checked_rem(rhs).ok_or_else(overflow)?
+            };
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        match self.peek_keyword(&["-", "+", "NOT", "HIGH", "LOW"]) {
+            Some(op) => {
+                self.pos += 1;
+                let value = self.unary()?;
+                Ok(match op.as_str() {
+                    "-" => value.checked_neg().ok_or_else(overflow)?,
+                    "+" => value,
+                    "NOT" => !value & 0xffff,
+                    "HIGH" => (value >> 8) & 0xff,
+                    _ => value & 0xff
+                })
+            }
+            None => self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<i64, String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+
+        match token {
+            Some(Token::Number(value)) => Ok(value),
+            Some(Token::Here) if self.asm.pc > 0xffff => Err(String::from("$ is past the end of memory")),
+            Some(Token::Here) => Ok(self.asm.pc as i64),
+            Some(Token::Text(text)) => match text.as_slice() {
+                [c] => Ok(*c as i64),
+                [hi, lo] => Ok(((*hi as i64) << 8) | *lo as i64),
+                _ => Err(String::from("only strings of one or two characters have a value"))
+            },
+            Some(Token::Symbol(name)) if !is_keyword(&name) => {
+                match self.asm.symbols.get(&name) {
+                    Some(&value) => Ok(value),
+                    None if !self.strict => Ok(0),
+                    None => Err(format!("undefined symbol {}", name))
+                }
+            }
+            Some(Token::Op('(')) => {
+                let value = self.or()?;
+                match self.tokens.get(self.pos) {
+                    Some(Token::Op(')')) => {
+                        self.pos += 1;
+                        Ok(value)
+                    }
+                    _ => Err(String::from("missing )"))
+                }
+            }
+            _ => Err(String::from("expected a value"))
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use crate::assembler::assemble;
+use crate::cpu::decoder::{decode, is_undocumented};
+use crate::cpu::intel8080::{Intel8080, StopReason};
+
+#[test]
+fn assemble_labels_and_directives() {
+    let program = assemble("
+        COUNT   EQU 3
+        BUFFER  EQU 2000H
+
+                ORG 100H
+        START:  LXI SP,STACK        ; set up the stack
+                MVI B,COUNT
+        LOOP:   DCR B
+                JNZ LOOP
+                LXI H,MESSAGE
+                JMP DONE
+        MESSAGE: DB 'Hi, there', 0DH, 0AH
+        TABLE:  DW START, BUFFER+1
+                DS 4
+        DONE:   HLT
+                DS 16
+        STACK:
+                END
+                DB 1, 2, 3
+    ").unwrap();
+
+    assert_eq!(program.origin, 0x100);
+    assert_eq!(program.symbols["COUNT"], 3);
+    assert_eq!(program.symbols["LOOP"], 0x105);
+    assert_eq!(program.symbols["MESSAGE"], 0x10f);
+    assert_eq!(program.symbols["TABLE"], 0x11a);
+    assert_eq!(program.symbols["DONE"], 0x122);
+    assert_eq!(program.symbols["STACK"], 0x133);
+
+    assert_eq!(&program.image[..0x0f], &[
+        0x31, 0x33, 0x01, // LXI SP,STACK
+        0x06, 0x03, // MVI B,COUNT
+        0x05, // DCR B
+        0xc2, 0x05, 0x01, // JNZ LOOP
+        0x21, 0x0f, 0x01, // LXI H,MESSAGE
+        0xc3, 0x22, 0x01 // JMP DONE
+    ]);
+    assert_eq!(&program.image[0x0f..0x1a], b"Hi, there\r\n");
+    assert_eq!(&program.image[0x1a..0x1e], &[0x00, 0x01, 0x01, 0x20]);
+    // DS leaves a zero gap inside the image but adds nothing after it.
+    assert_eq!(&program.image[0x1e..0x22], &[0; 4]);
+    assert_eq!(program.image.len(), 0x23);
+    assert_eq!(program.image[0x22], 0x76);
+
+    let mut machine = Intel8080::new();
+    machine.load_image(program.origin, &program.image).unwrap();
+    machine.pc = program.origin;
+    assert!(matches!(machine.run(), StopReason::Halted));
+    assert_eq!(machine.regs.b, 0);
+    assert_eq!(machine.regs.h, 0x01);
+    assert_eq!(machine.regs.l, 0x0f);
+    assert_eq!(machine.sp, 0x133);
+}
+
+#[test]
+fn assemble_expressions() {
+    let program = assemble("
+        BASE    EQU 1234H
+        MASK    EQU NOT 0FH AND 0FFH
+                MVI A,HIGH BASE
+                MVI B,LOW BASE
+                MVI C,(2 + 3) * 4 - 1
+                MVI D,MASK
+                MVI E,101B SHL 2 OR 1
+                MVI H,-1
+                MVI L,'A' + 1
+                LXI B,$
+                LXI D,17O MOD 5 + 10 / 3
+                DW 'AB', $
+    ").unwrap();
+
+    assert_eq!(program.image, vec![
+        0x3e, 0x12, 0x06, 0x34, 0x0e, 19, 0x16, 0xf0, 0x1e, 0x15, 0x26, 0xff, 0x2e, 0x42,
+        0x01, 0x0e, 0x00, 0x11, 0x03, 0x00, 0x42, 0x41, 0x14, 0x00
+    ]);
+}
+
+// Every documented opcode disassembles to text that assembles back to the
+// same bytes.
+#[test]
+fn assemble_every_instruction() {
+    for opcode in 0..=0xff_u8 {
+        let bytes = [opcode, 0x34, 0x12];
+        if is_undocumented(opcode) {
+            continue;
+        }
+
+        let (instruction, length) = decode(&bytes);
+
+        let text = instruction.to_string();
+        let program = assemble(&text).unwrap_or_else(|e| panic!("{}: {}", text, e));
+        assert_eq!(program.image, bytes[..length].to_vec(), "{}", text);
+    }
+}
+
+#[test]
+fn assemble_errors() {
+    let error = |source: &str| assemble(source).unwrap_err();
+
+    assert_eq!(error("NOP\nFOO A").line, 2);
+    assert_eq!(error("NOP\nFOO A").message, "unknown instruction FOO");
+    assert_eq!(error("JMP NOWHERE").message, "undefined symbol NOWHERE");
+    assert_eq!(error("X: NOP\nX: NOP").message, "X is defined more than once");
+    assert_eq!(error("MVI A,100H").message, "256 does not fit in a byte");
+    assert_eq!(error("MVI A,-256").message, "-256 does not fit in a byte");
+    assert_eq!(error("MVI A,-129").message, "-129 does not fit in a byte");
+    assert_eq!(error("LXI H,-8001H").message, "-32769 does not fit in a word");
+    assert_eq!(error("DW -8001H").message, "-32769 does not fit in a word");
+    assert_eq!(error("MOV A").message, "MOV takes 2 operands");
+    assert_eq!(error("PUSH SP").message, "invalid operands for PUSH: SP");
+    assert_eq!(error("MOV M,M").message, "invalid operands for MOV: M,M");
+    assert_eq!(error("ORG LATER\nLATER: NOP").message, "undefined symbol LATER");
+    assert_eq!(error("ORG 0FFFFH\nLXI H,0").message, "program runs past the end of memory");
+    assert_eq!(error("DB 'open").message, "unterminated string in ''open'");
+    assert_eq!(error("DW 65535*65535*65535*65535*65535").message, "expression is too large");
+    assert_eq!(error("DW 0-65535*65535*65535*32768-65535*65535*65535*32768").message, "expression is too large");
+    assert_eq!(error("ORG 0FFFFH\nDB 1\nX:").message, "X is past the end of memory");
+    assert_eq!(error("X EQU 0FFFFH+1").message, "65536 does not fit in a word");
+    assert_eq!(error("X EQU 300*300").message, "90000 does not fit in a word");
+    assert_eq!(error("ORG 0FFFFH\nDB 1\nX EQU $").message, "$ is past the end of memory");
+}
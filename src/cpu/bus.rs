@@ -0,0 +1,107 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::cpu::error::Error;
+
+// The memory side of the 8080's address bus. Every memory access made by
+// `Intel8080` goes through this trait, so an implementation is free to map
+// ROM, mirror regions or hang devices off particular addresses. Returning an
+// error stops the CPU with a fault instead of executing on bad data.
+pub trait Bus {
+    fn read(&mut self, addr: u16) -> Result<u8, Error>;
+    fn write(&mut self, addr: u16, value: u8) -> Result<(), Error>;
+}
+
+// A flat RAM: every address maps straight to the byte at that index.
+// `Intel8080::new` uses a 64 KiB vector so the whole address space is backed;
+// a shorter vector faults on accesses past its end.
+impl Bus for Vec<u8> {
+    fn read(&mut self, addr: u16) -> Result<u8, Error> {
+        self.get(addr as usize).copied().ok_or(Error::UnmappedAddress(addr))
+    }
+
+    fn write(&mut self, addr: u16, value: u8) -> Result<(), Error> {
+        match self.get_mut(addr as usize) {
+            Some(byte) => { *byte = value; Ok(()) }
+            None => Err(Error::UnmappedAddress(addr))
+        }
+    }
+}
+
+// Bits of the status byte the 8080 places on the data bus at the start of
+// every machine cycle.
+pub const STATUS_INTA: u8 = 0x01; // interrupt acknowledge
+pub const STATUS_WO: u8 = 0x02; // active low: set for reads, clear for writes
+pub const STATUS_STACK: u8 = 0x04; // address comes from SP
+pub const STATUS_HLTA: u8 = 0x08; // halt acknowledge
+pub const STATUS_OUT: u8 = 0x10; // output to a port
+pub const STATUS_M1: u8 = 0x20; // first cycle of an instruction
+pub const STATUS_INP: u8 = 0x40; // input from a port
+pub const STATUS_MEMR: u8 = 0x80; // memory read
+
+// The status byte of each kind of machine cycle.
+pub const INSTRUCTION_FETCH: u8 = STATUS_MEMR | STATUS_M1 | STATUS_WO;
+pub const MEMORY_READ: u8 = STATUS_MEMR | STATUS_WO;
+pub const MEMORY_WRITE: u8 = 0;
+pub const STACK_READ: u8 = STATUS_MEMR | STATUS_STACK | STATUS_WO;
+pub const STACK_WRITE: u8 = STATUS_STACK;
+pub const INPUT_READ: u8 = STATUS_INP | STATUS_WO;
+pub const OUTPUT_WRITE: u8 = STATUS_OUT;
+pub const INTERRUPT_ACKNOWLEDGE: u8 = STATUS_INTA | STATUS_M1 | STATUS_WO;
+pub const HALT_ACKNOWLEDGE: u8 = STATUS_HLTA | STATUS_MEMR | STATUS_WO;
+pub const INTERRUPT_ACKNOWLEDGE_WHILE_HALT: u8 = STATUS_INTA | STATUS_HLTA | STATUS_M1 | STATUS_WO;
+// Reading the operand bytes of an instruction supplied during interrupt
+// acknowledge, such as the address of a CALL.
+pub const INTERRUPT_OPERAND_READ: u8 = STATUS_INTA | STATUS_WO;
+
+// One machine cycle that used the bus. `m` numbers the cycle within its
+// instruction, from 1 for the fetch (or interrupt acknowledge). For port
+// cycles the port number appears on both halves of `addr`, as on the real
+// address bus; during halt acknowledge the data bus floats and reads 0xff.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusCycle {
+    pub m: u8,
+    pub status: u8,
+    pub addr: u16,
+    pub data: u8
+}
+
+// Receives every bus cycle of a CPU it is attached to with
+// `Intel8080::attach_bus_observer`, in the order the 8080 performs them. Cycles
+// in which the bus is idle, such as the last two of DAD, are not reported.
+pub trait BusObserver {
+    fn cycle(&mut self, cycle: &BusCycle);
+}
+
+// The 8080's READY input. A device keeps a clone (from
+// `Intel8080::ready_line`) and pulls the line low for a number of clock
+// cycles; the CPU then stretches its next machine cycle by that many wait
+// states.
+#[derive(Debug, Clone, Default)]
+pub struct ReadyLine(Rc<Cell<u64>>);
+
+impl ReadyLine {
+    pub fn hold_low(&self, cycles: u64) {
+        self.0.set(self.0.get() + cycles);
+    }
+
+    // Whether a device is holding the line low.
+    pub fn is_low(&self) -> bool {
+        self.0.get() > 0
+    }
+
+    pub(crate) fn release(&self) -> u64 {
+        self.0.replace(0)
+    }
+}
+
+// A device that can take the buses from the CPU, such as a DMA controller.
+// While `hold` is true the CPU finishes its current machine cycle, floats
+// its buses and acknowledges with HLDA; `transfer` then has the memory to
+// itself and returns the number of clock cycles it kept it for. HOLD is
+// sampled again after the next machine cycle, so a longer transfer is done
+// in several calls.
+pub trait BusMaster<B: Bus> {
+    fn hold(&self) -> bool;
+    fn transfer(&mut self, memory: &mut B) -> u64;
+}
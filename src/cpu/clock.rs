@@ -0,0 +1,109 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How fast the emulated clock runs against the host's, see
+// `Intel8080::set_clock_speed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSpeed {
+    // As fast as the host allows.
+    Turbo,
+    // Paced to the given frequency in Hz.
+    Hz(u64)
+}
+
+impl ClockSpeed {
+    pub const INTEL_8080: ClockSpeed = ClockSpeed::Hz(2_000_000);
+    pub const INTEL_8080A_1: ClockSpeed = ClockSpeed::Hz(3_125_000);
+
+    pub fn mhz(mhz: f64) -> ClockSpeed {
+        ClockSpeed::Hz((mhz * 1_000_000.0) as u64)
+    }
+}
+
+// The throttled speed defaults to the 2 MHz of the original part.
+impl Default for ClockSpeed {
+    fn default() -> Self {
+        ClockSpeed::INTEL_8080
+    }
+}
+
+// How a run kept up with its target clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpeedReport {
+    pub target: ClockSpeed,
+    pub cycles: u64,
+    pub elapsed: Duration
+}
+
+impl SpeedReport {
+    pub fn achieved_hz(&self) -> f64 {
+        if self.elapsed.is_zero() {
+            return 0.0;
+        }
+
+        self.cycles as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+// The emulated time is compared with the host's once per slice, and the
+// host sleeps off any lead. Falling further behind than MAX_LAG (the host
+// was busy, or the process stopped) starts a new schedule from the present
+// rather than running flat out to catch up. No sleep runs past the deadline
+// of the run.
+const SLICES_PER_SECOND: u64 = 100;
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+pub(crate) struct Pacer {
+    speed: ClockSpeed,
+    started: Instant,
+    start_cycles: u64,
+    base: Instant,
+    base_cycles: u64,
+    next_check: u64,
+    deadline: Option<Instant>
+}
+
+impl Pacer {
+    pub(crate) fn new(speed: ClockSpeed, cycles: u64, deadline: Option<Instant>) -> Pacer {
+        let now = Instant::now();
+        Pacer { speed, started: now, start_cycles: cycles, base: now, base_cycles: cycles, next_check: cycles, deadline }
+    }
+
+    // Called at every instruction boundary with the CPU's cycle count.
+    // Returns whether the host clock was consulted.
+    pub(crate) fn pace(&mut self, cycles: u64) -> bool {
+        if cycles < self.next_check {
+            return false;
+        }
+
+        self.sync(cycles);
+        true
+    }
+
+    // Wait until the host has caught up with `cycles`, at the end of a run
+    // as well as once per slice.
+    pub(crate) fn sync(&mut self, cycles: u64) {
+        let hz = match self.speed {
+            ClockSpeed::Turbo | ClockSpeed::Hz(0) => return,
+            ClockSpeed::Hz(hz) => hz
+        };
+        self.next_check = cycles + (hz / SLICES_PER_SECOND).max(1);
+
+        let emulated = (cycles - self.base_cycles) as u128 * 1_000_000_000 / hz as u128;
+        let mut due = self.base + Duration::from_nanos(emulated as u64);
+        if let Some(deadline) = self.deadline {
+            due = due.min(deadline);
+        }
+        let now = Instant::now();
+        if due > now {
+            thread::sleep(due - now);
+        } else if now - due > MAX_LAG {
+            self.base = now;
+            self.base_cycles = cycles;
+        }
+    }
+
+    pub(crate) fn report(&self, cycles: u64) -> SpeedReport {
+        SpeedReport { target: self.speed, cycles: cycles - self.start_cycles, elapsed: self.started.elapsed() }
+    }
+}
@@ -0,0 +1,194 @@
+// Turns raw opcode bytes into a typed `Instruction`. This is the one place
+// that knows what each of the 256 opcodes means; the executor, and anything
+// that wants to inspect code without running it, work from its output.
+//
+// Register pairs are BC, DE, HL and SP, with PSW (A and the flags) taking
+// the place of SP for PUSH and POP.
+
+use crate::cpu::{Reg8, RegPair};
+use crate::cpu::opcodes::OPCODES;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    NotZero,
+    Zero,
+    NoCarry,
+    Carry,
+    ParityOdd,
+    ParityEven,
+    Plus,
+    Minus
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Nop,
+    Lxi(RegPair, u16),
+    Stax(RegPair),
+    Ldax(RegPair),
+    Shld(u16),
+    Lhld(u16),
+    Sta(u16),
+    Lda(u16),
+    Inx(RegPair),
+    Dcx(RegPair),
+    Inr(Reg8),
+    Dcr(Reg8),
+    Mvi(Reg8, u8),
+    Dad(RegPair),
+    Rlc,
+    Rrc,
+    Ral,
+    Rar,
+    Daa,
+    Cma,
+    Stc,
+    Cmc,
+    Mov(Reg8, Reg8),
+    Hlt,
+    Add(Reg8),
+    Adc(Reg8),
+    Sub(Reg8),
+    Sbb(Reg8),
+    Ana(Reg8),
+    Xra(Reg8),
+    Ora(Reg8),
+    Cmp(Reg8),
+    Adi(u8),
+    Aci(u8),
+    Sui(u8),
+    Sbi(u8),
+    Ani(u8),
+    Xri(u8),
+    Ori(u8),
+    Cpi(u8),
+    Jmp(u16),
+    Jcc(Condition, u16),
+    Call(u16),
+    Ccc(Condition, u16),
+    Ret,
+    Rcc(Condition),
+    Rst(u8),
+    Pchl,
+    Push(RegPair),
+    Pop(RegPair),
+    Xthl,
+    Sphl,
+    Xchg,
+    In(u8),
+    Out(u8),
+    Ei,
+    Di
+}
+
+const REGISTERS: [Reg8; 8] = [Reg8::B, Reg8::C, Reg8::D, Reg8::E, Reg8::H, Reg8::L, Reg8::M, Reg8::A];
+const REGISTER_PAIRS: [RegPair; 4] = [RegPair::BC, RegPair::DE, RegPair::HL, RegPair::SP];
+const STACK_PAIRS: [RegPair; 4] = [RegPair::BC, RegPair::DE, RegPair::HL, RegPair::PSW];
+const CONDITIONS: [Condition; 8] = [
+    Condition::NotZero, Condition::Zero, Condition::NoCarry, Condition::Carry,
+    Condition::ParityOdd, Condition::ParityEven, Condition::Plus, Condition::Minus
+];
+
+// Decode the instruction starting at `bytes[0]`, returning it together with
+// its length in bytes. Operand bytes missing from the end of `bytes` read as
+// zero, so `decode(&[opcode])` is enough to learn an instruction's length.
+pub fn decode(bytes: &[u8]) -> (Instruction, usize) {
+    let byte = |i: usize| bytes.get(i).copied().unwrap_or(0);
+
+    let opcode = byte(0);
+    let data = byte(1);
+    let addr = ((byte(2) as u16) << 8) | (byte(1) as u16);
+
+    // Most opcodes keep their operands in fixed bit fields.
+    let dst = REGISTERS[((opcode >> 3) & 0x07) as usize];
+    let src = REGISTERS[(opcode & 0x07) as usize];
+    let pair = REGISTER_PAIRS[((opcode >> 4) & 0x03) as usize];
+    let condition = CONDITIONS[((opcode >> 3) & 0x07) as usize];
+
+    match opcode {
+        0x00 => (Instruction::Nop, 1),
+        0x01 | 0x11 | 0x21 | 0x31 => (Instruction::Lxi(pair, addr), 3),
+        0x02 | 0x12 => (Instruction::Stax(pair), 1),
+        0x0A | 0x1A => (Instruction::Ldax(pair), 1),
+        0x22 => (Instruction::Shld(addr), 3),
+        0x2A => (Instruction::Lhld(addr), 3),
+        0x32 => (Instruction::Sta(addr), 3),
+        0x3A => (Instruction::Lda(addr), 3),
+        0x03 | 0x13 | 0x23 | 0x33 => (Instruction::Inx(pair), 1),
+        0x0B | 0x1B | 0x2B | 0x3B => (Instruction::Dcx(pair), 1),
+        0x09 | 0x19 | 0x29 | 0x39 => (Instruction::Dad(pair), 1),
+        0x07 => (Instruction::Rlc, 1),
+        0x0F => (Instruction::Rrc, 1),
+        0x17 => (Instruction::Ral, 1),
+        0x1F => (Instruction::Rar, 1),
+        0x27 => (Instruction::Daa, 1),
+        0x2F => (Instruction::Cma, 1),
+        0x37 => (Instruction::Stc, 1),
+        0x3F => (Instruction::Cmc, 1),
+        op if op & 0xC7 == 0x04 => (Instruction::Inr(dst), 1),
+        op if op & 0xC7 == 0x05 => (Instruction::Dcr(dst), 1),
+        op if op & 0xC7 == 0x06 => (Instruction::Mvi(dst, data), 2),
+
+        0x76 => (Instruction::Hlt, 1),
+        0x40..=0x7F => (Instruction::Mov(dst, src), 1),
+
+        0x80..=0x87 => (Instruction::Add(src), 1),
+        0x88..=0x8F => (Instruction::Adc(src), 1),
+        0x90..=0x97 => (Instruction::Sub(src), 1),
+        0x98..=0x9F => (Instruction::Sbb(src), 1),
+        0xA0..=0xA7 => (Instruction::Ana(src), 1),
+        0xA8..=0xAF => (Instruction::Xra(src), 1),
+        0xB0..=0xB7 => (Instruction::Ora(src), 1),
+        0xB8..=0xBF => (Instruction::Cmp(src), 1),
+
+        0xC6 => (Instruction::Adi(data), 2),
+        0xCE => (Instruction::Aci(data), 2),
+        0xD6 => (Instruction::Sui(data), 2),
+        0xDE => (Instruction::Sbi(data), 2),
+        0xE6 => (Instruction::Ani(data), 2),
+        0xEE => (Instruction::Xri(data), 2),
+        0xF6 => (Instruction::Ori(data), 2),
+        0xFE => (Instruction::Cpi(data), 2),
+
+        0xC3 => (Instruction::Jmp(addr), 3),
+        0xCD => (Instruction::Call(addr), 3),
+        0xC9 => (Instruction::Ret, 1),
+        op if op & 0xC7 == 0xC2 => (Instruction::Jcc(condition, addr), 3),
+        op if op & 0xC7 == 0xC4 => (Instruction::Ccc(condition, addr), 3),
+        op if op & 0xC7 == 0xC0 => (Instruction::Rcc(condition), 1),
+        op if op & 0xC7 == 0xC7 => (Instruction::Rst((op >> 3) & 0x07), 1),
+
+        0xC1 | 0xD1 | 0xE1 | 0xF1 => (Instruction::Pop(STACK_PAIRS[((opcode >> 4) & 0x03) as usize]), 1),
+        0xC5 | 0xD5 | 0xE5 | 0xF5 => (Instruction::Push(STACK_PAIRS[((opcode >> 4) & 0x03) as usize]), 1),
+
+        0xD3 => (Instruction::Out(data), 2),
+        0xDB => (Instruction::In(data), 2),
+        0xE3 => (Instruction::Xthl, 1),
+        0xE9 => (Instruction::Pchl, 1),
+        0xEB => (Instruction::Xchg, 1),
+        0xF3 => (Instruction::Di, 1),
+        0xF9 => (Instruction::Sphl, 1),
+        0xFB => (Instruction::Ei, 1),
+
+        // Opcodes Intel never documented. The 8080 does not decode every bit
+        // of them, so each behaves like the documented instruction it
+        // differs from in an ignored bit.
+        0x08 | 0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38 => (Instruction::Nop, 1),
+        0xCB => (Instruction::Jmp(addr), 3),
+        0xD9 => (Instruction::Ret, 1),
+        0xDD | 0xED | 0xFD => (Instruction::Call(addr), 3),
+
+        _ => unreachable!("opcode {:02X} is not decoded", opcode)
+    }
+}
+
+// Whether `opcode` is one of the undocumented aliases of NOP, JMP, RET and
+// CALL.
+pub fn is_undocumented(opcode: u8) -> bool {
+    OPCODES[opcode as usize].undocumented
+}
+
+// Length in bytes of the instruction introduced by `opcode`.
+pub fn instruction_length(opcode: u8) -> usize {
+    OPCODES[opcode as usize].length as usize
+}
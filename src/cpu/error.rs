@@ -0,0 +1,48 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+// Everything that can go wrong while loading or running a program.
+#[derive(Debug)]
+pub enum Error {
+    // The program file could not be opened or read.
+    Io(io::Error),
+    // The program image (of the given size) does not fit in the 64 KiB
+    // address space.
+    ProgramTooLarge(usize),
+    // An image that would fit in memory was placed at an origin from which
+    // it runs past the top of the address space.
+    ImageOutOfRange { origin: u16, size: usize },
+    // Nothing on the bus answers at this address.
+    UnmappedAddress(u16)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "could not read program - {}", e),
+            Error::ProgramTooLarge(size) => {
+                write!(f, "program is {} bytes, larger than the 64K address space", size)
+            }
+            Error::ImageOutOfRange { origin, size } => {
+                write!(f, "{} bytes loaded at {:04X}H run past the end of memory", size, origin)
+            }
+            Error::UnmappedAddress(addr) => write!(f, "no memory at address {:04X}H", addr)
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
@@ -1,8 +1,10 @@
+use crate::cpu::bus::Bus;
+use crate::cpu::{Reg8, RegPair};
 use crate::cpu::intel8080::Intel8080;
 use crate::cpu::utils::*;
 
 
-pub fn add_to_accu(state: &mut Intel8080, byte: u8) {
+pub fn add_to_accu<B: Bus>(state: &mut Intel8080<B>, byte: u8) {
     // INSTRUCTION: ADD byte
     // DESCRIPTION: 
     //      The ADD In,truction adds one byte of data to the contents of the 
@@ -12,15 +14,16 @@ pub fn add_to_accu(state: &mut Intel8080, byte: u8) {
 
     let result = (state.regs.a as u16) + (byte as u16);
 
+    state.flags.aux_carry = half_carry(state.regs.a, byte, 0);
     state.flags.carry = (result > 0xff) as u8;
-    state.flags.zero = (((result as u8) & 0xff) == 0) as u8;
+    state.flags.zero = ((result as u8) == 0) as u8;
     state.flags.sign = (((result as u8) & 0x80) != 0) as u8;
-    state.flags.parity = parity(result);
+    state.flags.parity = parity(result & 0xff);
 
     state.regs.a = result as u8;
 }
 
-pub fn adc(state: &mut Intel8080, byte: u8) {
+pub fn adc<B: Bus>(state: &mut Intel8080<B>, byte: u8) {
     // INSTRUCTION: ADC byte
     // DESCRIPTION: 
     //      The ADC inst ruction adds one byte of data plus the setting of the 
@@ -30,418 +33,229 @@ pub fn adc(state: &mut Intel8080, byte: u8) {
 
     let result = (state.regs.a as u16) + (byte as u16) + (state.flags.carry as u16);
 
+    state.flags.aux_carry = half_carry(state.regs.a, byte, state.flags.carry);
     state.flags.carry = (result > 0xff) as u8;
-    state.flags.zero = (((result as u8) & 0xff) == 0) as u8;
+    state.flags.zero = ((result as u8) == 0) as u8;
     state.flags.sign = (((result as u8) & 0x80) != 0) as u8;
-    state.flags.parity = parity(result);
+    state.flags.parity = parity(result & 0xff);
 
     state.regs.a = result as u8;
 }
 
-pub fn lxi(state: &mut Intel8080, byte: char) {
+pub fn lxi<B: Bus>(state: &mut Intel8080<B>, pair: RegPair, data: u16) {
     // INSTRUCTION: LXI byte
     // DESCRIPTION:
     //      LXI is a three-byte instruction; its second and third bytes contain the source 
     //      data to be loaded into a register pair. LXI loads a register pair by copying its 
     //      second and third bytes into the specified destination register pair.
 
-    match byte {
-        'B' => {
-            // load bytes into register B and C
-            state.regs.b = state.memory[state.pc + 2];
-            state.regs.c = state.memory[state.pc + 1];
-        }
-        'D' => {
-            // load bytes into register D and E
-            state.regs.d = state.memory[state.pc + 2];
-            state.regs.e = state.memory[state.pc + 1];
-        }
-        'H' => {
-            // load bytes into register H and L
-            state.regs.h = state.memory[state.pc + 2];
-            state.regs.l = state.memory[state.pc + 1];
-        }
-        'S' => {
-            // load bytes into th stack pointer (SP)
-            state.sp = (((state.memory[state.pc + 2] as u16) << 8) | 
-                        (state.memory[state.pc + 1] as u16)) as usize;
-        }
-        _ => {}
-    }
+    state.set_pair(pair, data);
 }
 
-pub fn stax(state: &mut Intel8080, byte: char) {
+pub fn stax<B: Bus>(state: &mut Intel8080<B>, pair: RegPair) {
     // INSTRUCTION: STAX byte
     // DESCRIPTION: 
     //      The STAX insruction stores a copy of the contents of the accumulator into 
     //      the memory location addressed by register pai B or register pair D.
 
-    let mut addr = 0;
-    match byte {
-        'B' => {
-            // get the content of register pair B and C
-            // format them into an address in LE format.
-            addr = (((state.regs.b as u16) << 8) | 
-                    (state.regs.c as u16)) as usize;
-        }
-        'D' => {
-            // get the content of register pair B and C
-            // format them into an address in LE format.
-            addr = (((state.regs.d as u16) << 8) | 
-                    (state.regs.e as u16)) as usize;
-        }
-        _ => {}
-    }
-    
-    // get the value in the A register and store this
-    // value at the address created in the previous step.
-    state.memory[addr] = state.regs.a;
+    let addr = state.pair(pair);
+    state.write_byte(addr, state.regs.a);
 }
 
-pub fn mvi(state: &mut Intel8080, byte: char) {
+pub fn mvi<B: Bus>(state: &mut Intel8080<B>, reg: Reg8, data: u8) {
     // INSTRUCTION: MVI byte
     // DESCRIPTION:
     //      the immediate data byte is stored in register specified. 
     //      No condition flags are affected. 
 
-    match byte {
-        'B' => { state.regs.b = state.memory[state.pc + 1]; }
-        'C' => { state.regs.c = state.memory[state.pc + 1]; }
-        'D' => { state.regs.d = state.memory[state.pc + 1]; }
-        'E' => { state.regs.e = state.memory[state.pc + 1]; }
-        'H' => { state.regs.h = state.memory[state.pc + 1]; }
-        'L' => { state.regs.l = state.memory[state.pc + 1]; }
-        'A' => { state.regs.a = state.memory[state.pc + 1]; }
-        'M' => { 
-            let addr = (((state.regs.h as u16) << 8) | (state.regs.l as u16)) as usize;
-            state.memory[addr] = state.memory[state.pc + 1]; 
-        }
-        _ => {}
-    }   
+    state.set(reg, data);
 }
 
-pub fn inx(state: &mut Intel8080, byte: char) {
+pub fn inx<B: Bus>(state: &mut Intel8080<B>, pair: RegPair) {
     // INSTRUCTION: INX byte
     // DESCRIPTION: 
     //      INX adds one to the contents of the specified register pair.
 
-    match byte {
-        'B' => {
-            let value = (((state.regs.b as u16) << 8) | (state.regs.c as u16)) + 1;
-
-            state.regs.b = ((value & 0xff00) >> 8) as u8;
-            state.regs.c = (value & 0x00ff) as u8;
-        }
-        'D' => {
-            let value = (((state.regs.d as u16) << 8) | (state.regs.e as u16)) + 1;
-
-            state.regs.d = ((value & 0xff00) >> 8) as u8;
-            state.regs.e = (value & 0x00ff) as u8;
-        }
-        'H' => {
-            let value = (((state.regs.h as u16) << 8) | (state.regs.l as u16)) + 1;
-
-            state.regs.h = ((value & 0xff00) >> 8) as u8;
-            state.regs.l = (value & 0x00ff) as u8;
-        }
-        _ => {}
-    }
-
+    let value = state.pair(pair).wrapping_add(1);
+    state.set_pair(pair, value);
 }
 
-pub fn inr(state: &mut Intel8080, byte: char) {
+pub fn inr<B: Bus>(state: &mut Intel8080<B>, reg: Reg8) {
     // INSTRUCTION: INR byte
     // DESCRIPTION: 
     //      Increment the specified register by 1;
 
-    let mut result = 0;
-    match byte {
-        'B' => { result = (state.regs.b as u16) + 1; state.regs.b = result as u8; }
-        'C' => { result = (state.regs.c as u16) + 1; state.regs.c = result as u8; }
-        'D' => { result = (state.regs.d as u16) + 1; state.regs.d = result as u8; }
-        'E' => { result = (state.regs.e as u16) + 1; state.regs.e = result as u8; }
-        'H' => { result = (state.regs.h as u16) + 1; state.regs.h = result as u8; }
-        'L' => { result = (state.regs.l as u16) + 1; state.regs.l = result as u8; }
-        'A' => { result = (state.regs.a as u16) + 1; state.regs.a = result as u8; }
-        'M' => {
-            let addr = (((state.regs.h as u16) << 8) | (state.regs.l as u16)) as usize;
-            let result = (state.memory[addr] as u16) + 1;
-            
-            state.memory[addr] = result as u8;
-        }   
-        _ => {}
-    }
-    
-    state.flags.zero = (((result as u8) & 0xff) == 0) as u8;
-    state.flags.sign = (((result as u8) & 0x80) != 0) as u8;
-    state.flags.parity = parity(result);
+    let result = state.get(reg).wrapping_add(1);
+    state.set(reg, result);
+
+    state.flags.aux_carry = ((result & 0x0f) == 0) as u8;
+    state.flags.zero = (result == 0) as u8;
+    state.flags.sign = ((result & 0x80) != 0) as u8;
+    state.flags.parity = parity(result as u16);
 }
 
-pub fn dcr(state: &mut Intel8080, byte: char) {
+pub fn dcr<B: Bus>(state: &mut Intel8080<B>, reg: Reg8) {
     // INSTRUCTION: DCR byte
     // DESCRIPTION:
     //      The value in the specified register is decremented by 1;
 
-    let mut result = 0;
-    match byte {
-        'B' => { result = (state.regs.b as i16) - 1; state.regs.b = result as u8; }
-        'C' => { result = (state.regs.c as i16) - 1; state.regs.c = result as u8; }
-        'D' => { result = (state.regs.d as i16) - 1; state.regs.d = result as u8; }
-        'E' => { result = (state.regs.e as i16) - 1; state.regs.e = result as u8; }
-        'H' => { result = (state.regs.h as i16) - 1; state.regs.h = result as u8; }
-        'L' => { result = (state.regs.l as i16) - 1; state.regs.l = result as u8; }
-        'A' => { result = (state.regs.a as i16) - 1; state.regs.a = result as u8; }
-        'M' => {
-                let addr = (((state.regs.h as u16) << 8) | (state.regs.l as u16)) as usize;
-                let result = (state.memory[addr] as u16) - 1;
-
-                state.memory[addr] = result as u8;
-        }
-        _ => {}
-    }
-
-    state.flags.zero = (((result as u8) & 0xff) == 0) as u8;
-    state.flags.sign = (((result as u8) & 0x80) != 0) as u8;
+    let result = state.get(reg).wrapping_sub(1);
+    state.set(reg, result);
+
+    // DCR adds 0xff, which carries out of bit 3 unless the low nibble was 0.
+    state.flags.aux_carry = ((result & 0x0f) != 0x0f) as u8;
+    state.flags.zero = (result == 0) as u8;
+    state.flags.sign = ((result & 0x80) != 0) as u8;
     state.flags.parity = parity(result as u16);
 }
 
-pub fn dad(state: &mut Intel8080, byte: char) {
+pub fn dad<B: Bus>(state: &mut Intel8080<B>, pair: RegPair) {
     // INSTRUCTION: DAD byte
     // DESCRIPTION:
     //      The 16-bit number in the specified register pair is added to the 
     //      16-bit number held in the H and L registers using two's complement 
     //      arithmetic. The result replaces the contents in the H and L registers. 
 
-    let mut result = 0;
-    let hl = ((state.regs.h as u32) << 8) | (state.regs.l as u32);
-    match byte {
-        'B' => {
-            let bc = ((state.regs.b as u32) << 8) | (state.regs.c as u32);
-            
-            result =  bc + hl;
-            state.regs.h = ((result & 0x0000ff00) >> 8) as u8;
-            state.regs.l = (result & 0x000000ff) as u8;
-        }
-        'D' => {
-            let de = ((state.regs.d as u32) << 8) | (state.regs.e as u32);
-
-            result =  de + hl;
-            state.regs.h = ((result & 0x0000ff00) >> 8) as u8;
-            state.regs.l = (result & 0x000000ff) as u8;
-        }
-        'H' => {
-            let result =  hl << 1;
-            state.regs.h = ((result & 0x0000ff00) >> 8) as u8;
-            state.regs.l = (result & 0x000000ff) as u8;
-        }
-        'S' => {
-            let result =  (state.sp as u32) + hl;
-            state.regs.h = ((result & 0x0000ff00) >> 8) as u8;
-            state.regs.l = (result & 0x000000ff) as u8;
-        }
-        _ => {}
-    }
+    let result = (state.pair(RegPair::HL) as u32) + (state.pair(pair) as u32);
+    state.set_pair(RegPair::HL, result as u16);
 
     // set the carry flag
-    state.flags.carry = ((result & 0xffff0000) > 0) as u8;
+    state.flags.carry = (result > 0xffff) as u8;
 }
 
-pub fn ldax(state: &mut Intel8080, byte: char) {
+pub fn ldax<B: Bus>(state: &mut Intel8080<B>, pair: RegPair) {
     // INSTRUCTION: LDAX byte
     // DESCRIPTION: 
     //      The contents of the memory location addressed by the specified register
     //      pair replace the contents of the accumulator.
     
-    let mut addr = 0;
-    match byte {
-        'B' => { addr = (((state.regs.b as u16) << 8) | ((state.regs.c) as u16)) as usize; }
-        'D' => { addr = (((state.regs.d as u16) << 8) | ((state.regs.e) as u16)) as usize; }
-        _ => {}
-    }
-
-    state.regs.a = state.memory[addr];
+    let addr = state.pair(pair);
+    state.regs.a = state.read_byte(addr);
 }
 
-pub fn dcx(state: &mut Intel8080, byte: char) {
+pub fn dcx<B: Bus>(state: &mut Intel8080<B>, pair: RegPair) {
     // INSTRUCTION: DCX B
     // DESCRIPTION: 
     //      The 16-bit number held in the specified register pair is decremented by one.
 
-    match byte {
-        'B' => {
-            let value = (((state.regs.b as u16) << 8) | (state.regs.c as u16)) - 1;
-
-            state.regs.b = ((value & 0xff00) >> 8) as u8;
-            state.regs.c = (value & 0x00ff) as u8;
-        }
-        'D' => {
-            let value = (((state.regs.d as u16) << 8) | (state.regs.e as u16)) - 1;
-
-            state.regs.d = ((value & 0xff00) >> 8) as u8;
-            state.regs.e = (value & 0x00ff) as u8;
-        }
-        'H' => {
-            let value = (((state.regs.h as u16) << 8) | (state.regs.l as u16)) - 1;
-
-            state.regs.h = ((value & 0xff00) >> 8) as u8;
-            state.regs.l = (value & 0x00ff) as u8;
-        }
-        _ => {}
-    }
+    let value = state.pair(pair).wrapping_sub(1);
+    state.set_pair(pair, value);
 }
 
-pub fn mov_m(state: &mut Intel8080, byte: char) {
-    let addr = (((state.regs.h as u16) << 8) | (state.regs.l as u16)) as usize;
-    match byte {
-        'B' => { state.memory[addr] = state.regs.b; }
-        'C' => { state.memory[addr] = state.regs.c; }
-        'D' => { state.memory[addr] = state.regs.d; }
-        'E' => { state.memory[addr] = state.regs.e; }
-        'H' => { state.memory[addr] = state.regs.h; }
-        'L' => { state.memory[addr] = state.regs.l; }
-        'A' => { state.memory[addr] = state.regs.a; }
-        _ => {}
-    }
-}
-
-pub fn sub_accu(state: &mut Intel8080, byte: u8) {
-    let result: u16 = (state.regs.a - byte) as u16;
+pub fn sub_accu<B: Bus>(state: &mut Intel8080<B>, byte: u8) {
+    let result = (state.regs.a as u16).wrapping_sub(byte as u16);
 
+    state.flags.aux_carry = half_carry(state.regs.a, !byte, 1);
     state.flags.carry = (result > 0xff) as u8;
-    state.flags.zero = (((result as u8) & 0xff) == 0) as u8;
+    state.flags.zero = ((result as u8) == 0) as u8;
     state.flags.sign = (((result as u8) & 0x80) != 0) as u8;
-    state.flags.parity = parity(result as u16);
+    state.flags.parity = parity(result & 0xff);
 
     state.regs.a = result as u8;
 }
 
-pub fn sbb(state: &mut Intel8080, byte: u8) {
-    let result = (state.regs.a as u16) - ((byte as u16) + (state.flags.carry as u16));
+pub fn sbb<B: Bus>(state: &mut Intel8080<B>, byte: u8) {
+    let result = (state.regs.a as u16).wrapping_sub((byte as u16) + (state.flags.carry as u16));
 
+    state.flags.aux_carry = half_carry(state.regs.a, !byte, 1 - state.flags.carry);
     state.flags.carry = (result > 0xff) as u8;
-    state.flags.zero = ((result as u16 & 0xffff) == 0) as u8;
-    state.flags.sign = ((result as u16 & 0x8000) != 0) as u8;
-    state.flags.parity = parity(result);
+    state.flags.zero = ((result as u8) == 0) as u8;
+    state.flags.sign = (((result as u8) & 0x80) != 0) as u8;
+    state.flags.parity = parity(result & 0xff);
 
     state.regs.a = result as u8;
 }
 
-pub fn ana(state: &mut Intel8080, byte: u8) {
+pub fn ana<B: Bus>(state: &mut Intel8080<B>, byte: u8) {
     let result = (state.regs.a as u16) & (byte as u16);
 
-    state.flags.carry = (result > 0xff) as u8;
-    state.flags.zero = (((result as u8) & 0xff) == 0) as u8;
+    // The 8080 sets AC to the OR of bit 3 of the two operands.
+    state.flags.aux_carry = (((state.regs.a | byte) & 0x08) != 0) as u8;
+    state.flags.carry = 0;
+    state.flags.zero = ((result as u8) == 0) as u8;
     state.flags.sign = (((result as u8) & 0x80) != 0) as u8;
-    state.flags.parity = parity(result);
+    state.flags.parity = parity(result & 0xff);
 
     state.regs.a = result as u8;
 }
 
-pub fn xra(state: &mut Intel8080, byte: u8) {
+pub fn xra<B: Bus>(state: &mut Intel8080<B>, byte: u8) {
     let result = (state.regs.a as u16) ^ (byte as u16);
 
-    state.flags.carry = (result > 0xff) as u8;
-    state.flags.zero = (((result as u8) & 0xff) == 0) as u8;
+    state.flags.aux_carry = 0;
+    state.flags.carry = 0;
+    state.flags.zero = ((result as u8) == 0) as u8;
     state.flags.sign = (((result as u8) & 0x80) != 0) as u8;
-    state.flags.parity = parity(result);
+    state.flags.parity = parity(result & 0xff);
 
     state.regs.a = result as u8;
 }
 
-pub fn ora(state: &mut Intel8080, byte: u8) {
+pub fn ora<B: Bus>(state: &mut Intel8080<B>, byte: u8) {
     let result = (state.regs.a as u16) | (byte as u16);
 
-    state.flags.carry = (result > 0xff) as u8;
-    state.flags.zero = (((result as u8) & 0xff) == 0) as u8;
+    state.flags.aux_carry = 0;
+    state.flags.carry = 0;
+    state.flags.zero = ((result as u8) == 0) as u8;
     state.flags.sign = (((result as u8) & 0x80) != 0) as u8;
-    state.flags.parity = parity(result);
+    state.flags.parity = parity(result & 0xff);
 
     state.regs.a = result as u8;
 }
 
-pub fn cmp(state: &mut Intel8080, byte: u8) {
-    let result = (state.regs.a as u16) - (byte as u16);
+pub fn cmp<B: Bus>(state: &mut Intel8080<B>, byte: u8) {
+    // INSTRUCTION: CMP byte
+    // DESCRIPTION:
+    //      The byte is subtracted from the accumulator to set the condition 
+    //      flags; the accumulator itself is left unchanged.
+
+    let result = (state.regs.a as u16).wrapping_sub(byte as u16);
 
+    state.flags.aux_carry = half_carry(state.regs.a, !byte, 1);
     state.flags.carry = (result > 0xff) as u8;
-    state.flags.zero = (((result as u8) & 0xff) == 0) as u8;
+    state.flags.zero = ((result as u8) == 0) as u8;
     state.flags.sign = (((result as u8) & 0x80) != 0) as u8;
-    state.flags.parity = parity(result);
-
-    state.regs.a = result as u8;
+    state.flags.parity = parity(result & 0xff);
 }
 
-pub fn pop(state: &mut Intel8080, byte: char) {
-    match byte {
-        'B' => {
-            state.regs.c = state.memory[state.sp];
-            state.regs.b = state.memory[state.sp + 1];
-        }
-        'D' => {
-            state.regs.e = state.memory[state.sp];
-            state.regs.d = state.memory[state.sp + 1];
-        }
-        'H' => {
-            state.regs.h = state.memory[state.sp];
-            state.regs.l = state.memory[state.sp + 1];
-        }
-        'P' => {
-            state.regs.a = state.memory[state.sp + 1];
-
-            // get the content of the memory location specified by the stack pointer
-            let result = state.memory[state.sp] as u16;
-
-            state.flags.carry = (result > 0xff) as u8;
-            state.flags.zero = (((result as u8) & 0xff) == 0) as u8;
-            state.flags.sign = (((result as u8) & 0x80) != 0) as u8;
-            state.flags.parity = parity(result);
-        }
-        _ => {}
-    }
-
-    state.sp += 2;
+pub fn pop<B: Bus>(state: &mut Intel8080<B>, pair: RegPair) {
+    // INSTRUCTION: POP rp
+    // DESCRIPTION:
+    //      The byte at the top of the stack becomes the low half of the pair
+    //      (the flags, for PSW) and the byte above it the high half.
+
+    let lsb = state.read_stack(state.sp);
+    let msb = state.read_stack(state.sp.wrapping_add(1));
+    state.sp = state.sp.wrapping_add(2);
+
+    state.set_pair(pair, ((msb as u16) << 8) | (lsb as u16));
 }
 
-pub fn push(state: &mut Intel8080, byte: char) {
-    match byte {
-        'B' => {
-            state.memory[state.sp - 1] = state.regs.b;
-            state.memory[state.sp - 2] = state.regs.c;
-        }
-        'D' => {
-            state.memory[state.sp - 1] = state.regs.d;
-            state.memory[state.sp - 2] = state.regs.e;
-        }
-        'H' => {
-            state.memory[state.sp - 1] = state.regs.h;
-            state.memory[state.sp - 2] = state.regs.l;
-        }
-        'P' => {
-            state.memory[state.sp - 1] = state.regs.a;
-
-            let psw = state.flags.zero             |
-                        state.flags.sign      << 1 |
-                        state.flags.parity    << 2 |
-                        state.flags.carry     << 3 |
-                        state.flags.aux_carry << 4;
-
-            state.memory[state.sp - 2] = psw;
-        }
-        _ => {}
-    }
-
-    state.sp -= 2;
+pub fn push<B: Bus>(state: &mut Intel8080<B>, pair: RegPair) {
+    // INSTRUCTION: PUSH rp
+    // DESCRIPTION:
+    //      The high half of the pair (A, for PSW) is stored below the stack
+    //      pointer and the low half below that; SP is left pointing at it.
+
+    let value = state.pair(pair);
+    state.write_stack(state.sp.wrapping_sub(1), (value >> 8) as u8);
+    state.write_stack(state.sp.wrapping_sub(2), value as u8);
+
+    state.sp = state.sp.wrapping_sub(2);
 }
 
-pub fn rst(state: &mut Intel8080, code: u8) {
-    let addr = state.pc + 1; // Address of the next instruction
+pub fn rst<B: Bus>(state: &mut Intel8080<B>, code: u8) {
+    // The PC has already been advanced past the RST, or left where it was
+    // if the RST came from an interrupt.
+    let addr = state.pc;
 
     let msb = ((addr & 0xff00) >> 8) as u8;
     let lsb = (addr & 0x00ff) as u8;
     
-    state.memory[state.sp - 1] = msb;
-    state.memory[state.sp - 2] = lsb;
+    state.write_stack(state.sp.wrapping_sub(1), msb);
+    state.write_stack(state.sp.wrapping_sub(2), lsb);
 
-    state.pc = ((code as u16) << 3) as usize;
+    state.pc = (code as u16) << 3;
 
-    state.sp -= 2;
+    state.sp = state.sp.wrapping_sub(2);
 }
\ No newline at end of file
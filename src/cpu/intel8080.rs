@@ -1,1066 +1,1038 @@
+use std::collections::HashSet;
 use std::fs::File;
+use std::ops::RangeInclusive;
 use std::path::Path;
 use std::io::Read;
-
-use crate::cpu::{ConditionFlags, Register};
+use std::time::{Duration, Instant};
+
+use crate::cpu::{ConditionFlags, Reg8, RegPair, Register};
+use crate::cpu::bus::*;
+use crate::cpu::clock::{ClockSpeed, Pacer, SpeedReport};
+use crate::cpu::error::Error;
+use crate::cpu::io::{IoDevice, Unconnected};
+use crate::cpu::opcodes::OPCODES;
+use crate::cpu::scheduler::Scheduler;
 use crate::cpu::utils::*;
 use crate::cpu::instructions::*;
+use crate::cpu::decoder::{decode, instruction_length, is_undocumented, Instruction};
+
+// Why a run of the CPU came to an end.
+#[derive(Debug)]
+pub enum StopReason {
+    // HLT was executed and no interrupt is pending to resume the CPU.
+    Halted,
+    // An instruction could not complete. The PC and SP are put back to where
+    // they were before it, so the PC points at the failed instruction; other
+    // registers and memory may already have been changed by it.
+    Fault(Error),
+    // An undocumented opcode was fetched from `pc` while they are set to
+    // trap. It has not been executed and the PC still points at it.
+    UndocumentedOpcode { pc: u16, opcode: u8 },
+    // The PC reached a breakpoint. The instruction there has not run yet.
+    Breakpoint { pc: u16 },
+    // One of the limits passed to `Intel8080::run_with_limits` ran out.
+    BudgetExhausted(Budget)
+}
+
+// The limit that ended a run with StopReason::BudgetExhausted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Budget {
+    Instructions,
+    Cycles,
+    Time
+}
+
+// Bounds on a call to `Intel8080::run_with_limits`, checked at instruction
+// boundaries. Limits left at None never run out, e.g.
+// `RunLimits::new().instructions(1_000_000).timeout(Duration::from_secs(5))`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RunLimits {
+    pub instructions: Option<u64>,
+    pub cycles: Option<u64>,
+    pub deadline: Option<Instant>
+}
+
+impl RunLimits {
+    pub fn new() -> Self {
+        RunLimits::default()
+    }
 
+    pub fn instructions(mut self, max: u64) -> Self {
+        self.instructions = Some(max);
+        self
+    }
+
+    pub fn cycles(mut self, max: u64) -> Self {
+        self.cycles = Some(max);
+        self
+    }
+
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
 
-pub struct Intel8080 {
+    // A deadline `timeout` from now.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.deadline(Instant::now() + timeout)
+    }
+}
+
+// How the CPU treats the opcodes Intel never documented (0x08, 0x10, 0x18,
+// 0x20, 0x28, 0x30, 0x38, 0xCB, 0xD9, 0xDD, 0xED and 0xFD).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UndocumentedOpcodes {
+    // Run them as the silicon does, as aliases of NOP, JMP, RET and CALL.
+    Execute,
+    // Stop with StopReason::UndocumentedOpcode instead. Useful for catching
+    // Z80 code, or a jump into data.
+    Trap
+}
+
+// What the registers and RAM hold when power is applied, see
+// `Intel8080::power_on`. A real 8080 comes up with garbage in both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerOn {
+    // Everything cleared, as `Intel8080::new` leaves it.
+    Zeroed,
+    // Every register and memory byte set to the same value, e.g. 0xff.
+    Pattern(u8),
+    // Pseudo-random contents, the same for the same seed.
+    Random(u64)
+}
+
+// Describes the instruction executed by a call to `Intel8080::step`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Step {
+    pub pc: u16,
+    pub opcode: u8,
+    pub instruction: Instruction,
+    pub length: usize,
+    pub branch_taken: bool,
+    pub cycles: u8,
+    pub wait_states: u64,
+    pub held_cycles: u64,
+    pub interrupt: bool
+}
+
+pub struct Intel8080<B: Bus = Vec<u8>> {
     pub regs: Register,
     pub flags: ConditionFlags,
-    pub pc: usize,
-    pub sp: usize,
+    pub pc: u16,
+    pub sp: u16,
     pub int_enable: u8,
-    pub memory: Vec<u8>
+    // T-states elapsed, wait states and hold time included; `wait_states`
+    // counts those spent stalled on slow memory, slow ports or a low READY
+    // line and `held_cycles` those the buses were given up to a bus master.
+    pub cycles: u64,
+    pub wait_states: u64,
+    pub held_cycles: u64,
+    pub memory: B,
+    io: Box<dyn IoDevice>,
+    interrupt_request: Option<[u8; 3]>,
+    ei_delay: bool,
+    halted: bool,
+    fault: Option<Error>,
+    undocumented: UndocumentedOpcodes,
+    observer: Option<Box<dyn BusObserver>>,
+    m_cycle: u8,
+    memory_wait_states: Vec<(RangeInclusive<u16>, u8)>,
+    port_wait_states: [u8; 256],
+    ready: ReadyLine,
+    stalled: u64,
+    bus_master: Option<Box<dyn BusMaster<B>>>,
+    held: u64,
+    breakpoints: HashSet<u16>,
+    breakpoint_stop: Option<u16>,
+    scheduler: Scheduler<B>,
+    clock_speed: ClockSpeed,
+    speed_report: SpeedReport
 }
 
 impl Intel8080 {
     pub fn new() -> Self {
+        Intel8080::with_bus(vec![0_u8; 0x10000]) // 65 KB of Memory
+    }
+}
+
+impl<B: Bus> Intel8080<B> {
+    pub fn with_bus(memory: B) -> Self {
         Intel8080 {
             regs: Register::new(),
             flags: ConditionFlags::new(),
-            pc: 0_usize,
-            sp: 0_usize,
+            pc: 0_u16,
+            sp: 0_u16,
             int_enable: 0,
-            memory: vec![0_u8; 0x10000] // 65 KB of Memory
+            cycles: 0,
+            wait_states: 0,
+            held_cycles: 0,
+            memory,
+            io: Box::new(Unconnected),
+            interrupt_request: None,
+            ei_delay: false,
+            halted: false,
+            fault: None,
+            undocumented: UndocumentedOpcodes::Execute,
+            observer: None,
+            m_cycle: 0,
+            memory_wait_states: Vec::new(),
+            port_wait_states: [0; 256],
+            ready: ReadyLine::default(),
+            stalled: 0,
+            bus_master: None,
+            held: 0,
+            breakpoints: HashSet::new(),
+            breakpoint_stop: None,
+            scheduler: Scheduler::new(),
+            clock_speed: ClockSpeed::Turbo,
+            speed_report: SpeedReport { target: ClockSpeed::Turbo, cycles: 0, elapsed: Duration::ZERO }
         }
     }
 
-    pub fn load_program(&mut self, file_name: &str) {
-        let mut f = match File::open(Path::new(file_name)) {
-            Ok(file) => file,
-            Err(e) => panic!("Could not open file - {}", e)
+    pub fn attach_io<D: IoDevice + 'static>(&mut self, device: D) {
+        self.io = Box::new(device);
+    }
+
+    // Report every machine cycle to `observer` from now on. Without one the
+    // CPU only works at instruction granularity.
+    pub fn attach_bus_observer<O: BusObserver + 'static>(&mut self, observer: O) {
+        self.observer = Some(Box::new(observer));
+    }
+
+    pub fn detach_bus_observer(&mut self) {
+        self.observer = None;
+    }
+
+    // Insert `wait_states` into every memory cycle addressing `range`, as a
+    // slow ROM or memory board would. Where ranges overlap the one set last
+    // applies, and ranges it covers entirely are dropped.
+    pub fn set_memory_wait_states(&mut self, range: RangeInclusive<u16>, wait_states: u8) {
+        self.memory_wait_states.retain(|(r, _)| r.start() < range.start() || r.end() > range.end());
+        self.memory_wait_states.push((range, wait_states));
+    }
+
+    pub fn clear_memory_wait_states(&mut self) {
+        self.memory_wait_states.clear();
+    }
+
+    pub fn set_port_wait_states(&mut self, port: u8, wait_states: u8) {
+        self.port_wait_states[port as usize] = wait_states;
+    }
+
+    // A handle on the READY input for devices that need to stall the CPU.
+    pub fn ready_line(&self) -> ReadyLine {
+        self.ready.clone()
+    }
+
+    // Connect a device that can request the buses with HOLD.
+    pub fn attach_bus_master<M: BusMaster<B> + 'static>(&mut self, master: M) {
+        self.bus_master = Some(Box::new(master));
+    }
+
+    pub fn set_undocumented_opcodes(&mut self, policy: UndocumentedOpcodes) {
+        self.undocumented = policy;
+    }
+
+    // Pull the RESET input. Only the PC, INTE and the HALT and HOLD states
+    // are cleared; registers and memory keep whatever they held, so a
+    // program restarting at 0 must not assume anything about them.
+    pub fn reset(&mut self) {
+        self.pc = 0;
+        self.int_enable = 0;
+        self.ei_delay = false;
+        self.halted = false;
+        self.fault = None;
+        self.breakpoint_stop = None;
+    }
+
+    // Simulate applying power: fill the registers, SP and every writable
+    // memory byte according to `policy`, then reset. Addresses the bus
+    // refuses to write, such as ROM, keep their contents.
+    pub fn power_on(&mut self, policy: PowerOn) {
+        let mut state = match policy {
+            PowerOn::Random(seed) => mix(seed),
+            _ => 0
+        };
+        let mut next = || match policy {
+            PowerOn::Zeroed => 0,
+            PowerOn::Pattern(value) => value,
+            PowerOn::Random(_) => {
+                // xorshift64
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 32) as u8
+            }
         };
 
-        f.read(&mut self.memory).unwrap();
+        for reg in [Reg8::B, Reg8::C, Reg8::D, Reg8::E, Reg8::H, Reg8::L, Reg8::A] {
+            self.set(reg, next());
+        }
+        self.flags = ConditionFlags::unpack(next());
+        self.sp = ((next() as u16) << 8) | (next() as u16);
+
+        for addr in 0..=0xffff {
+            let _ = self.memory.write(addr, next());
+        }
+
+        self.reset();
     }
-    
-    pub fn run(&mut self) {
-        while self.memory[self.pc] != 0x76 { // while opcode != HLT (0x76)
-            match self.memory[self.pc] {
-                0x00 => { self.pc += 1; } // NOP
-                0x01 => { lxi(self, 'B'); self.pc += 3; }
-                0x02 => { stax(self, 'B'); self.pc += 1; }
-                0x03 => { inx(self, 'B'); self.pc += 1; }
-                0x04 => { inr(self, 'B'); self.pc += 1; }
-                0x05 => { dcr(self, 'B'); self.pc += 1; }
-                0x06 => { mvi(self, 'B'); self.pc += 2; }
-                0x07 => { 
-                    // INSTRUCTION: RLC
-                    // DESCRIPTION:
-                    //      The contents of the accumulator are rotated one bit position to 
-                    //      the left, with the high-order bit being transferred to the 
-                    //      low-order bit position of the accumulator.
-
-                    // compute carry and use it to compute the new value to
-                    // be assigned to the accumulator (A) register.
-                    let carry = ((self.regs.a & 0x80) >> 7) as u8;
-                    self.regs.a = ((self.regs.a << 1) | carry) as u8;
-
-                    // The Carry bit is set equal to the high-order bit of the accumulator.
-                    self.flags.carry = carry;
-
-                    self.pc += 1;
-                }
-                0x08 => { self.pc += 1; }
-                0x09 => { dad(self, 'B'); self.pc += 1; }
-                0x0A => { ldax(self, 'B'); self.pc += 1; }
-                0x0B => { dcx(self, 'B'); self.pc += 1; }
-                0x0C => { inr(self, 'C'); self.pc += 1; }
-                0x0D => { dcr(self, 'C'); self.pc += 1; }
-                0x0E => { mvi(self, 'C'); self.pc += 2; }
-                0x0F => {
-                    // INSTRUCTION: RRC
-                    // DESCRIPTION:
-                    //      The contents of the accumulator are rotated one bit position to 
-                    //      the right, with the low-order bit being transferred to the 
-                    //      high-order bit position of the accumulator.
-
-                    // compute carry and use it to compute the new value to
-                    // be assigned to the accumulator (A) register.
-                    let carry = (self.regs.a & 0x01) as u8;
-                    self.regs.a = ((self.regs.a >> 1) | (carry << 7)) as u8;
-
-                    // The Carry bit is set equal to the high-order bit of the accumulator.
-                    self.flags.carry = carry;
-
-                    self.pc += 1;
-                }
 
+    pub fn load_program(&mut self, file_name: &str) -> Result<(), Error> {
+        let mut f = File::open(Path::new(file_name))?;
 
-                0x10 => { self.pc += 1; }
-                0x11 => { lxi(self, 'D'); self.pc += 3; }
-                0x12 => { stax(self, 'D');  self.pc += 1; }
-                0x13 => { inx(self, 'D'); self.pc += 1; }
-                0x14 => { inr(self, 'D'); self.pc += 1; }
-                0x15 => { dcr(self, 'D'); self.pc += 1; }
-                0x16 => { mvi(self, 'D'); self.pc += 2; }
-                0x17 => {
-                    // INSTRUCTION: RAL
-                    // DESCRIPTION: 
-                    //      The contents of the accumulator are rotated one bit position to the left.
-                    //      The high-order bit of the accumulator replaces the Carry bit, while the 
-                    //      Carry bit replaces the high-order bit of the accumulator.
-                    
-                    let temp = self.flags.carry;
-                    let carry = ((self.regs.a & 0x80) >> 7) as u8;
-                    self.regs.a = ((self.regs.a << 1) | (temp << 7)) as u8;
-
-                    // The Carry bit is set equal to the high-order bit of the accumulator.
-                    self.flags.carry = carry;
-
-                    self.pc += 1;
+        let mut program = Vec::new();
+        f.read_to_end(&mut program)?;
 
-                }
-                0x18 => { self.pc += 1; }
-                0x19 => { dad(self, 'D'); self.pc += 1; }
-                0x1A => { ldax(self, 'D'); self.pc += 1; }
-                0x1B => { dcx(self, 'D'); self.pc += 1; }
-                0x1C => { inr(self, 'E'); self.pc += 1; }
-                0x1D => { dcr(self, 'E'); self.pc += 1; }
-                0x1E => { mvi(self, 'E'); self.pc += 2; }
-                0x1F => {
-                    // INSTRUCTION: RAR
-                    // DESCRIPTION: 
-                    //      The contents of the accumulator are rotated one bit position 
-                    //      to the right. The low-order bit of the accumulator replaces the
-                    //      carry bit, while the carry bit replaces the high-order bit of
-                    //      the accumulator.
-
-                    let temp = self.flags.carry;
-                    let carry = (self.regs.a & 0x01) as u8;
-                    self.regs.a = ((self.regs.a >> 1) | (temp << 7)) as u8;
-
-                    // The Carry bit is set equal to the high-order bit of the accumulator.
-                    self.flags.carry = carry;
-
-                    self.pc += 1;
-                }
+        self.load_image(0, &program)
+    }
 
+    // Copy `image` into memory starting at `origin`, e.g. the output of the
+    // assembler.
+    pub fn load_image(&mut self, origin: u16, image: &[u8]) -> Result<(), Error> {
+        if image.len() > 0x10000 {
+            return Err(Error::ProgramTooLarge(image.len()));
+        }
+        if origin as usize + image.len() > 0x10000 {
+            return Err(Error::ImageOutOfRange { origin, size: image.len() });
+        }
 
-                0x20 => { self.pc += 1; }
-                0x21 => { lxi(self, 'H'); self.pc += 3; }
-                0x22 => {
-                    // INSTRUCTION: SHLD
-                    // DESCRIPTION: 
-                    //      The contents of the L register are stored at the memory address 
-                    //      formed by concatenati ng HI AD 0 with LOW ADO. The contents of 
-                    //      the H register are stored at the next higher memory address.
+        for (offset, byte) in image.iter().enumerate() {
+            self.memory.write(origin + offset as u16, *byte)?;
+        }
 
-                    let mut addr = (((self.memory[self.pc + 2] as u16) << 8) | 
-                                    (self.memory[self.pc + 1] as u16)) as usize;
+        Ok(())
+    }
 
-                    self.memory[addr] = self.regs.l; addr += 1;
-                    self.memory[addr] = self.regs.h;
+    // Memory accesses made while executing an instruction. A failed access
+    // is remembered (the first one wins) and reported by `step` once the
+    // instruction is over; reads that fail see a floating bus.
+    pub(crate) fn read_byte(&mut self, addr: u16) -> u8 {
+        self.bus_read(addr, MEMORY_READ)
+    }
 
-                    self.pc += 3;
-                }
-                0x23 => { inx(self, 'H'); self.pc += 1; }
-                0x24 => { inr(self, 'H'); self.pc += 1; }
-                0x25 => { dcr(self, 'H'); self.pc += 1; }
-                0x26 => { mvi(self, 'H'); self.pc += 2; }
-                0x27 => {
-                    // INSTRUCTION: DAA
-                    // DESCRIPTION:
-                    //      The DAA intruction adjusts the eight-bit value in the accumulator 
-                    //      to form two four-bit binary coded decimal digits.
-
-                    if (self.regs.a & 0x0f) > 9 || self.flags.aux_carry == 1 {
-                        self.regs.a += 6;
-                        self.flags.aux_carry = 1;
-                    }
+    pub(crate) fn write_byte(&mut self, addr: u16, value: u8) {
+        self.bus_write(addr, value, MEMORY_WRITE)
+    }
 
-                    let mut ho_nibble = (self.regs.a & 0xf0) >> 4;
-                    if ho_nibble > 9 || self.flags.carry == 1 {
-                        ho_nibble += 6;
-                        self.regs.a = (self.regs.a & 0x0f) | (ho_nibble << 4);
-                        self.flags.carry = 1;
-                    }
+    // The same, for accesses addressed by SP.
+    pub(crate) fn read_stack(&mut self, addr: u16) -> u8 {
+        self.bus_read(addr, STACK_READ)
+    }
 
-                    self.flags.zero = ((self.regs.a as u16 & 0xffff) == 0) as u8;
-                    self.flags.sign = ((self.regs.a as u16 & 0x8000) != 0) as u8;
-                    self.flags.parity = parity(self.regs.a as u16);
+    pub(crate) fn write_stack(&mut self, addr: u16, value: u8) {
+        self.bus_write(addr, value, STACK_WRITE)
+    }
 
-                    self.pc += 1;
-                }
-                0x28 => { self.pc += 1; }
-                0x29 => { dad(self, 'H'); self.pc += 1; }
-                0x2A => {
-                    // INSTRUCTION: LHLD
-                    // DESCRIPTION: 
-                    //      The byte at the memory address formed by concatenating HI ADD 
-                    //      with LOW ADD replaces the contents of the L register. The byte 
-                    //      at the next higher memory address replaces the contents of the 
-                    //      H register.
-
-                    let mut addr = (((self.memory[self.pc + 2] as u16) << 8) | 
-                                    (self.memory[self.pc + 1] as u16)) as usize;
-                    
-                    self.regs.l = self.memory[addr]; addr += 1;
-                    self.regs.h = self.memory[addr];
-
-                    self.pc += 3;
-                }
-                0x2B => { dcx(self, 'H'); self.pc += 1; }
-                0x2C => { inr(self, 'L'); self.pc += 1; }
-                0x2D => { dcr(self, 'L'); self.pc += 1; }
-                0x2E => { mvi(self, 'L'); self.pc += 2; }
-                0x2F => {
-                    // INSTRUCTION: CMA
-                    // DESCRIPTION: 
-                    //      Each bit of the contents of the accumulator is complemented 
-                    //      (producing the one's complement). 
-
-                    self.regs.a = !self.regs.a;
-
-                    self.pc += 1;
-                }
+    fn bus_read(&mut self, addr: u16, status: u8) -> u8 {
+        let value = match self.memory.read(addr) {
+            Ok(value) => value,
+            Err(e) => {
+                self.fault.get_or_insert(e);
+                0xff
+            }
+        };
 
+        self.machine_cycle(status, addr, value);
+        value
+    }
 
-                0x30 => { self.pc += 1; }
-                0x31 => { lxi(self, 'S'); self.pc += 3; }
-                0x32 => {
-                    // INSTRUCTION: STA
-                    // DESCRIPTION: 
-                    //      The contents of the accumulator replace the byte at the memory 
-                    //      address formed by concatenating HI ADD with LOW ADD.
+    fn bus_write(&mut self, addr: u16, value: u8, status: u8) {
+        if let Err(e) = self.memory.write(addr, value) {
+            self.fault.get_or_insert(e);
+        }
 
-                    let addr = (((self.memory[self.pc + 2] as u16) << 8) | 
-                                (self.memory[self.pc + 1] as u16)) as usize;
+        self.machine_cycle(status, addr, value);
+    }
 
-                    self.memory[addr] = self.regs.a;
+    fn machine_cycle(&mut self, status: u8, addr: u16, data: u8) {
+        // Number the cycle within the current instruction, count the wait
+        // states it is stretched by and hand it to the observer, if there is
+        // one. The CPU is already idle during halt acknowledge, so a READY
+        // request carries over to the cycle after it.
+        self.m_cycle += 1;
+        if status != HALT_ACKNOWLEDGE {
+            self.stalled += self.ready.release() + self.region_wait_states(status, addr) as u64;
+        }
 
-                    self.pc += 3;
-                }
-                0x33 => { self.sp += 1; self.pc += 1; }
-                0x34 => { inr(self, 'M');  self.pc += 1; }
-                0x35 => { dcr(self, 'M'); self.pc += 1; }
-                0x36 => { mvi(self, 'M'); self.pc += 2; }
-                0x37 => { self.flags.carry = 1; self.pc += 1; }
-                0x38 => { self.pc += 1; }
-                0x39 => { dad(self, 'S'); self.pc += 1;}
-                0x3A => {
-                    // INSTRUCTION: LDA
-                    // DESCRIPTION: 
-                    //      LDA load~ the accumulator with a copy of the byte at the location 
-                    //      specified In bytes two and three of the LDA instruction.
-                    let addr = (((self.memory[self.pc + 2] as u16) << 8) | 
-                                (self.memory[self.pc + 1] as u16)) as usize;
-
-                    println!("{:04x}: LDA {:04x}", self.pc, addr);
-                    self.regs.a = self.memory[addr];
-
-                    self.pc += 3;
-                }
-                0x3B => { self.sp -= 1; self.pc += 1; }
-                0x3C => { inr(self, 'A'); self.pc += 1; }
-                0x3D => { dcr(self, 'A'); self.pc += 1; }
-                0x3E => { mvi(self, 'A'); self.pc += 2; }
-                0x3F => { self.flags.carry = !self.flags.carry; self.pc += 1; }
-
-
-                0x40 => { self.pc += 1; }
-                0x41 => { self.regs.b = self.regs.c; self.pc += 1; }
-                0x42 => { self.regs.b = self.regs.d; self.pc += 1; }
-                0x43 => { self.regs.b = self.regs.e; self.pc += 1; }
-                0x44 => { self.regs.b = self.regs.h; self.pc += 1; }
-                0x45 => { self.regs.b = self.regs.l; self.pc += 1; }
-                0x46 => {
-                    // INSTRUCTION: MOV B, M
-                    // DESCRIPTION: move from memory into B
-                    let addr = (((self.regs.h as u16) << 8) | (self.regs.l as u16)) as usize;
-                    self.regs.b = self.memory[addr];
-
-                    self.pc += 1;
-                }
-                0x47 => { self.regs.b = self.regs.a; self.pc += 1; }
-                0x48 => { self.regs.c = self.regs.b; self.pc += 1; }
-                0x49 => { self.pc += 1; }
-                0x4A => { self.regs.c = self.regs.d; self.pc += 1; }
-                0x4B => { self.regs.c = self.regs.e; self.pc += 1; }
-                0x4C => { self.regs.c = self.regs.h; self.pc += 1; }
-                0x4D => { self.regs.c = self.regs.l; self.pc += 1; }
-                0x4E => {
-                    // INSTRUCTION: MOV C, M
-                    let addr = (((self.regs.h as u16) << 8) | (self.regs.l as u16)) as usize;
-                    self.regs.c = self.memory[addr];
-
-                    self.pc += 1;
-                }
-                0x4F => { self.regs.c = self.regs.a; self.pc += 1; }
+        if let Some(observer) = self.observer.as_mut() {
+            observer.cycle(&BusCycle { m: self.m_cycle, status, addr, data });
+        }
 
+        self.service_hold();
+    }
 
-                0x50 => { self.regs.d = self.regs.b; self.pc += 1; }
-                0x51 => { self.regs.d = self.regs.c; self.pc += 1; }
-                0x52 => { self.pc += 1; }
-                0x53 => { self.regs.d = self.regs.e; self.pc += 1; }
-                0x54 => { self.regs.d = self.regs.h; self.pc += 1; }
-                0x55 => { self.regs.d = self.regs.l; self.pc += 1; }
-                0x56 => {
-                    // INSTRUCTION: MOV D, M
-                    let addr = (((self.regs.h as u16) << 8) | (self.regs.l as u16)) as usize;
-                    self.regs.d = self.memory[addr];
+    fn service_hold(&mut self) {
+        // HOLD is sampled in every machine cycle and granted for one
+        // transfer at a time, so a bus master that never lets go still
+        // leaves the CPU a cycle in between and the run loop can stop.
+        if let Some(master) = self.bus_master.as_mut() {
+            if master.hold() {
+                self.held += master.transfer(&mut self.memory);
+            }
+        }
+    }
 
-                    self.pc += 1;
-                }
-                0x57 => { self.regs.d = self.regs.a; self.pc += 1; }
-                0x58 => { self.regs.e = self.regs.b; self.pc += 1; }
-                0x59 => { self.regs.e = self.regs.c; self.pc += 1; }
-                0x5A => { self.regs.e = self.regs.d; self.pc += 1; }
-                0x5B => { self.pc += 1; }
-                0x5C => { self.regs.e = self.regs.h; self.pc += 1; }
-                0x5D => { self.regs.e = self.regs.l; self.pc += 1; }
-                0x5E => {
-                    // INSTRUCTION: MOV E, M
-                    let addr = (((self.regs.h as u16) << 8) | (self.regs.l as u16)) as usize;
-                    self.regs.e = self.memory[addr];
-
-                    self.pc += 1;
-                }
-                0x5F => { self.regs.e = self.regs.a; self.pc += 1; }
+    fn count_stalls(&mut self) -> (u64, u64) {
+        // Add the wait states and held cycles of the current instruction to
+        // the totals, including one that stopped before it could run.
+        let (wait_states, held_cycles) = (self.stalled, self.held);
+        self.stalled = 0;
+        self.held = 0;
+        self.cycles += wait_states + held_cycles;
+        self.wait_states += wait_states;
+        self.held_cycles += held_cycles;
+        (wait_states, held_cycles)
+    }
+    
+    pub fn interrupt(&mut self, opcode: u8) {
+        // Raise the INT line. The request is held until the CPU accepts it,
+        // at which point `opcode` (normally RST n) is executed as though it
+        // had been placed on the data bus during the INTA cycle.
+        self.interrupt_instruction(&[opcode]);
+    }
 
-                
-                0x60 => { self.regs.h = self.regs.b; self.pc += 1; }
-                0x61 => { self.regs.h = self.regs.c; self.pc += 1; }
-                0x62 => { self.regs.h = self.regs.d; self.pc += 1; }
-                0x63 => { self.regs.h = self.regs.e; self.pc += 1; }
-                0x64 => { self.pc += 1; }
-                0x65 => { self.regs.h = self.regs.l; self.pc += 1; }
-                0x66 => {
-                    // INSTRUCTION: MOV H, M
-                    let addr = (((self.regs.h as u16) << 8) | (self.regs.l as u16)) as usize;
-                    self.regs.h = self.memory[addr];
-
-                    self.pc += 1;
-                }
-                0x67 => { self.regs.h = self.regs.a; self.pc += 1; }
-                0x68 => { self.regs.l = self.regs.b; self.pc += 1; }
-                0x69 => { self.regs.l = self.regs.c; self.pc += 1; }
-                0x6A => { self.regs.l = self.regs.d; self.pc += 1; }
-                0x6B => { self.regs.l = self.regs.e; self.pc += 1; }
-                0x6C => { self.regs.l = self.regs.h; self.pc += 1; }
-                0x6D => { self.pc += 1; }
-                0x6E => {
-                    // INSTRUCTION: MOV L, M
-                    let addr = (((self.regs.h as u16) << 8) | (self.regs.l as u16)) as usize;
-                    self.regs.l = self.memory[addr];
-
-                    self.pc += 1;
-                }
-                0x6F => { self.regs.l = self.regs.a; self.pc += 1; }
-
-
-                0x70 => { mov_m(self, 'B'); self.pc += 1; }
-                0x71 => { mov_m(self, 'C'); self.pc += 1; }
-                0x72 => { mov_m(self, 'D'); self.pc += 1; }
-                0x73 => { mov_m(self, 'E'); self.pc += 1; }
-                0x74 => { mov_m(self, 'H'); self.pc += 1; }
-                0x75 => { mov_m(self, 'L'); self.pc += 1; }
-                0x76 => {}
-                0x77 => { mov_m(self, 'A'); self.pc += 1; }
-                0x78 => { self.regs.a = self.regs.b; self.pc += 1; }
-                0x79 => { self.regs.a = self.regs.c; self.pc += 1; }
-                0x7A => { self.regs.a = self.regs.d; self.pc += 1; }
-                0x7B => { self.regs.a = self.regs.e; self.pc += 1; }
-                0x7C => { self.regs.a = self.regs.h; self.pc += 1; }
-                0x7D => { self.regs.a = self.regs.l; self.pc += 1; }
-                0x7E => {
-                    // INSTRUCTION: MOV A, M
-                    let addr = (((self.regs.h as u16) << 8) | (self.regs.l as u16)) as usize;
-                    self.regs.a = self.memory[addr];
-
-                    self.pc += 1;
-                }
-                0x7F => { self.pc += 1; }
-            
-
-                0x80 => { add_to_accu(self, self.regs.b); self.pc += 1; }
-                0x81 => { add_to_accu(self, self.regs.c); self.pc += 1; }
-                0x82 => { add_to_accu(self, self.regs.d); self.pc += 1; }
-                0x83 => { add_to_accu(self, self.regs.e); self.pc += 1; }
-                0x84 => { add_to_accu(self, self.regs.h); self.pc += 1; }
-                0x85 => { add_to_accu(self, self.regs.l); self.pc += 1; }
-                0x86 => {
-                    // INSTRUCTION: ADD M
-                    let addr = (((self.regs.h as u16) << 8) | (self.regs.l as u16)) as usize;
-                    add_to_accu(self, self.memory[addr]);
-
-                    self.pc += 1;
-                }
-                0x87 => { add_to_accu(self, self.regs.a); self.pc += 1; }
-                0x88 => { adc(self, self.regs.b); self.pc += 1; }
-                0x89 => { adc(self, self.regs.c); self.pc += 1; }
-                0x8A => { adc(self, self.regs.d); self.pc += 1; }
-                0x8B => { adc(self, self.regs.e); self.pc += 1; }
-                0x8C => { adc(self, self.regs.h); self.pc += 1; }
-                0x8D => { adc(self, self.regs.l); self.pc += 1; }
-                0x8E => {
-                    // INSTRUCTION: ADC M
-                    let addr = (((self.regs.h as u16) << 8) | (self.regs.l as u16)) as usize;
-                    adc(self, self.memory[addr]);
-
-                    self.pc += 1;
-                }
-                0x8F => { adc(self, self.regs.a); self.pc += 1; }
-                
+    pub fn interrupt_instruction(&mut self, bytes: &[u8]) {
+        // Like `interrupt`, for controllers such as the 8259 that answer with
+        // a whole instruction, typically a CALL. Each byte is read in its own
+        // INTA cycle; bytes the instruction needs beyond `bytes` read as a
+        // floating bus. A CALL pushes the address of the interrupted
+        // instruction, since the PC does not move during INTA.
+        let mut instruction = [0xff; 3];
+        for (byte, value) in instruction.iter_mut().zip(bytes) {
+            *byte = *value;
+        }
 
-                0x90 => { sub_accu(self, self.regs.b); self.pc += 1; }
-                0x91 => { sub_accu(self, self.regs.c); self.pc += 1; }
-                0x92 => { sub_accu(self, self.regs.d); self.pc += 1; }
-                0x93 => { sub_accu(self, self.regs.e); self.pc += 1; }
-                0x94 => { sub_accu(self, self.regs.h); self.pc += 1; }
-                0x95 => { sub_accu(self, self.regs.l); self.pc += 1; }
-                0x96 => {
-                    // INSTRUCTION: SUB M
-                    let addr = (((self.regs.h as u16) << 8) | (self.regs.l as u16)) as usize;
-                    sub_accu(self, self.memory[addr]); 
-
-                    self.pc += 1;
-                }
-                0x97 => { sub_accu(self, self.regs.a); self.pc += 1; }
-                0x98 => { sbb(self, self.regs.b); self.pc += 1; }
-                0x99 => { sbb(self, self.regs.c); self.pc += 1; }
-                0x9A => { sbb(self, self.regs.d); self.pc += 1; }
-                0x9B => { sbb(self, self.regs.e); self.pc += 1; }
-                0x9C => { sbb(self, self.regs.h); self.pc += 1; }
-                0x9D => { sbb(self, self.regs.l); self.pc += 1; }
-                0x9E => {
-                    // INSTRUCTION: SBB M
-                    let addr = (((self.regs.h as u16) << 8) | (self.regs.l as u16)) as usize;
-                    sbb(self, self.memory[addr]);
-
-                    self.pc += 1;
-                }
-                0x9F => { sbb(self, self.regs.a); self.pc += 1; }
+        self.interrupt_request = Some(instruction);
+    }
 
+    pub fn interrupt_pending(&self) -> bool {
+        self.interrupt_request.is_some()
+    }
 
-                0xA0 => { ana(self, self.regs.b); self.pc += 1; }
-                0xA1 => { ana(self, self.regs.c); self.pc += 1; }
-                0xA2 => { ana(self, self.regs.d); self.pc += 1; }
-                0xA3 => { ana(self, self.regs.e); self.pc += 1; }
-                0xA4 => { ana(self, self.regs.h); self.pc += 1; }
-                0xA5 => { ana(self, self.regs.l); self.pc += 1; }
-                0xA6 => {
-                    // INSTRUCTION: ANA M
-                    let addr = (((self.regs.h as u16) << 8) | (self.regs.l as u16)) as usize;
-                    ana(self, self.memory[addr]);
+    fn interrupt_ready(&self) -> bool {
+        // Interrupts are sampled at instruction boundaries, except for the
+        // one straight after EI.
+        self.interrupt_request.is_some() && self.int_enable == 1 && !self.ei_delay
+    }
 
-                    self.pc += 1;
-                }
-                0xA7 => { println!("{:04x}: ANA A", self.pc); ana(self, self.regs.a); self.pc += 1; }
-                0xA8 => { xra(self, self.regs.b); self.pc += 1; }
-                0xA9 => { xra(self, self.regs.c); self.pc += 1; }
-                0xAA => { xra(self, self.regs.d); self.pc += 1; }
-                0xAB => { xra(self, self.regs.e); self.pc += 1; }
-                0xAC => { xra(self, self.regs.h); self.pc += 1; }
-                0xAD => { xra(self, self.regs.l); self.pc += 1; }
-                0xAE => {
-                    // INSTRUCTION: XRA M
-                    let addr = (((self.regs.h as u16) << 8) | (self.regs.l as u16)) as usize;
-                    xra(self, self.memory[addr]);
-
-                    self.pc += 1;
-                }
-                0xAF => { xra(self, self.regs.a); self.pc += 1; }
-
-                0xB0 => { ora(self, self.regs.b); self.pc += 1; }
-                0xB1 => { ora(self, self.regs.c); self.pc += 1; }
-                0xB2 => { ora(self, self.regs.d); self.pc += 1; }
-                0xB3 => { ora(self, self.regs.e); self.pc += 1; }
-                0xB4 => { ora(self, self.regs.h); self.pc += 1; }
-                0xB5 => { ora(self, self.regs.l); self.pc += 1; }
-                0xB6 => {
-                    // INSTRUCTION: ORA C
-                    let addr = (((self.regs.h as u16) << 8) | (self.regs.l as u16)) as usize;
-                    ora(self, self.memory[addr]);
-
-                    self.pc += 1;
-                }
-                0xB7 => { ora(self, self.regs.a); self.pc += 1; }
-                0xB8 => { cmp(self, self.regs.b); self.pc += 1; }
-                0xB9 => { cmp(self, self.regs.c); self.pc += 1; }
-                0xBA => { cmp(self, self.regs.d); self.pc += 1; }
-                0xBB => { cmp(self, self.regs.e); self.pc += 1; }
-                0xBC => { cmp(self, self.regs.h); self.pc += 1; }
-                0xBD => { cmp(self, self.regs.l); self.pc += 1; }
-                0xBE => {
-                    // INSTRUCTION: CMP M
-                    let addr = (((self.regs.h as u16) << 8) | (self.regs.l as u16)) as usize;
-                    cmp(self, self.memory[addr]);
-
-                    self.pc += 1;
-                }
-                0xBF => { cmp(self, self.regs.a); self.pc += 1; }
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
 
+    // A handle on the machine's event queue, for devices to keep.
+    pub fn scheduler(&self) -> Scheduler<B> {
+        self.scheduler.clone()
+    }
 
-                0xC0 => {
-                    // INSTRUCTION: RNZ
-                    if self.flags.zero == 0 {
-                        let lsb = self.memory[self.sp];
-                        let msb = self.memory[self.sp + 1];
+    fn service_events(&mut self) {
+        self.scheduler.advance(self.cycles);
+        while let Some(event) = self.scheduler.take_due(self.cycles) {
+            event(self);
+        }
+    }
 
-                        let addr = (((msb as u16) << 8) | (lsb as u16)) as usize;
-                        self.pc = addr;
-                        self.sp += 2;
-                    } else {
-                        self.pc += 1;
-                    }
-                }
-                0xC1 => { pop(self, 'B'); self.pc += 1; }
-                0xC2 => {
-                    // INSTRUCTION: JNZ
-                    if self.flags.zero == 0 {
-                        let addr = (((self.memory[self.pc + 2] as u16) << 8) | 
-                                    (self.memory[self.pc + 1] as u16)) as usize;
-
-                        println!("{:04x}: JNZ {:04x}", self.pc, addr);
-                        self.pc = addr;
-                    } else {
-                        self.pc += 3;
-                    }
-                }
-                0xC3 => {
-                    // INSTRUCTION: JMP
-                    let addr = (((self.memory[self.pc + 2] as u16) << 8) | 
-                                (self.memory[self.pc + 1] as u16)) as usize;
+    // Let a halted CPU's clock run on to the next scheduled event, but no
+    // further than `limit`. Returns false if there is nothing to wait for.
+    fn idle_until_event(&mut self, limit: u64) -> bool {
+        match self.scheduler.next_due() {
+            Some(at) if at <= limit => {
+                self.cycles = self.cycles.max(at);
+                true
+            }
+            _ => false
+        }
+    }
 
-                    self.pc = addr;
-                }
-                0xC4 => {
-                    // INSTRUCTION: CNZ
-                    if self.flags.zero == 0 {
-                        let next_instr_addr = self.pc + 3; // Address of the next instruction
-                        let msb = ((next_instr_addr & 0xff00) >> 8) as u8;
-                        let lsb = (next_instr_addr & 0x00ff) as u8;
-
-                        self.memory[self.sp - 1] = msb; 
-                        self.memory[self.sp - 2] = lsb;
-
-                        let addr = (((self.memory[self.pc + 2] as u16) << 8) | 
-                                    (self.memory[self.pc + 1] as u16)) as usize;
-
-                        self.pc = addr;
-                        self.sp -= 2;
-                    } else { self.pc += 3; }
-                }
-                0xC5 => { push(self, 'B'); self.pc += 1; }
-                0xC6 => {
-                    // INSTRUCTION: ADI
-                    let result = (self.regs.a as u16) + (self.memory[self.pc + 1] as u16);
-                    
-                    self.flags.carry = (result > 0xff) as u8;
-                    self.flags.zero = (((result as u8) & 0xff) == 0) as u8;
-                    self.flags.sign = (((result as u8) & 0x80) != 0) as u8;
-                    self.flags.parity = parity(result);
-
-                    self.regs.a = result as u8;
-                    self.pc += 2;
-                }
-                0xC7 => { rst(self, 0); }
-                0xC8 => {
-                    // INSTRUCTION: RZ
-                    if self.flags.zero == 1 {
-                        let lsb = self.memory[self.sp];
-                        let msb = self.memory[self.sp + 1];
-
-                        let addr = (((msb as u16) << 8) | (lsb as u16)) as usize;
-                        self.pc = addr;
-                        self.sp += 2;
-
-                    } else {
-                        self.pc += 1;
+    // Pace `run` and `run_with_limits` to `speed`, e.g. ClockSpeed::default()
+    // for a 2 MHz 8080. The CPU starts out in ClockSpeed::Turbo.
+    // `run_for_cycles` is never throttled: its caller already decides how
+    // much to run and when.
+    pub fn set_clock_speed(&mut self, speed: ClockSpeed) {
+        self.clock_speed = speed;
+    }
+
+    // The target and achieved speed of the last `run` or `run_with_limits`.
+    pub fn speed_report(&self) -> SpeedReport {
+        self.speed_report
+    }
+
+    // Stop runs before executing the instruction at `addr`.
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.remove(&addr);
+    }
+
+    pub fn run(&mut self) -> StopReason {
+        self.run_with_limits(RunLimits::new())
+    }
+
+    pub fn run_with_limits(&mut self, limits: RunLimits) -> StopReason {
+        // Run until something stops the CPU or a limit runs out. A run that
+        // stopped at a breakpoint resumes by executing the instruction there;
+        // otherwise a breakpoint at the PC a run starts from stops it before
+        // anything runs. A halted CPU waits for scheduled events, which may
+        // interrupt it.
+        let mut pacer = Pacer::new(self.clock_speed, self.cycles, limits.deadline);
+        let reason = self.run_paced(limits, &mut pacer);
+        pacer.sync(self.cycles);
+        self.speed_report = pacer.report(self.cycles);
+
+        reason
+    }
+
+    fn run_paced(&mut self, limits: RunLimits, pacer: &mut Pacer) -> StopReason {
+        let start = self.cycles;
+        let mut executed = 0_u64;
+        let mut boundaries = 0_u64;
+        let mut resume_from = self.breakpoint_stop.take();
+        loop {
+            if limits.instructions.is_some_and(|max| executed >= max) {
+                return StopReason::BudgetExhausted(Budget::Instructions);
+            }
+            if limits.cycles.is_some_and(|max| self.cycles - start >= max) {
+                return StopReason::BudgetExhausted(Budget::Cycles);
+            }
+            // Reading the clock costs more than most instructions, so unless
+            // pacing may just have slept it is only read now and then.
+            let paced = pacer.pace(self.cycles);
+            if (paced || boundaries.is_multiple_of(256)) && limits.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return StopReason::BudgetExhausted(Budget::Time);
+            }
+            boundaries += 1;
+            if !self.halted && resume_from != Some(self.pc) && self.breakpoints.contains(&self.pc) {
+                self.breakpoint_stop = Some(self.pc);
+                return StopReason::Breakpoint { pc: self.pc };
+            }
+            resume_from = None;
+
+            match self.step() {
+                Ok(_) => { executed += 1; }
+                Err(StopReason::Halted) => {
+                    let limit = limits.cycles.map_or(u64::MAX, |max| start + max);
+                    if !self.idle_until_event(limit) {
+                        if self.scheduler.next_due().is_some() {
+                            self.cycles = limit;
+                            return StopReason::BudgetExhausted(Budget::Cycles);
+                        }
+                        return StopReason::Halted;
                     }
                 }
-                0xC9 => {
-                    // INSTRUCTION: RET
-                    let lsb = self.memory[self.sp];
-                    let msb = self.memory[self.sp + 1];
+                Err(reason) => return reason
+            }
+        }
+    }
 
-                    let addr = (((msb as u16) << 8) | (lsb as u16)) as usize;
-                    self.pc = addr;
-                    self.sp += 2;
-                }
-                0xCA => {
-                    // INSTRUCTION: JZ
-                    if self.flags.zero == 1 {
-                        let addr = (((self.memory[self.pc + 2] as u16) << 8) | 
-                                    (self.memory[self.pc + 1] as u16)) as usize;
-
-                        self.pc = addr;
-                    } else {
-                        self.pc += 3;
+    pub fn run_for_cycles(&mut self, budget: u64) -> Result<u64, StopReason> {
+        // Run until at least `budget` T-states have elapsed, stopping at the
+        // first instruction boundary past it, and return the T-states used.
+        // A halted CPU still sees the clock go by, so it idles until the
+        // next scheduled event or through whatever is left of the budget.
+        // Anything else that stops the CPU ends the run and is returned.
+        let start = self.cycles;
+        while self.cycles - start < budget {
+            match self.step() {
+                Ok(_) => {}
+                Err(StopReason::Halted) => {
+                    if !self.idle_until_event(start + budget) {
+                        self.cycles = start + budget;
                     }
                 }
-                0xCB => { self.pc += 1; }
-                0xCC => {
-                    // INSTRUCTION: CZ
-                    if self.flags.zero == 1 {
-                        let next_instr_addr = self.pc + 3;
-                        let msb = ((next_instr_addr & 0xff00) >> 8) as u8;
-                        let lsb = (next_instr_addr & 0x00ff) as u8;
-
-                        self.memory[self.sp - 1] = msb; 
-                        self.memory[self.sp - 2] = lsb;
-
-                        let addr = (((self.memory[self.pc + 2] as u16) << 8) | 
-                                    (self.memory[self.pc + 1] as u16)) as usize;
-
-                        self.pc = addr;
-                        self.sp -= 2;
-                    } else { self.pc += 3; }
-                }
-                0xCD => {
-                    // INSTRUCTION: CALL
-                    let next_instr_addr = self.pc + 3;
-                    let msb = ((next_instr_addr & 0xff00) >> 8) as u8;
-                    let lsb = (next_instr_addr & 0x00ff) as u8;
+                Err(reason) => return Err(reason)
+            }
+        }
 
-                    self.memory[self.sp - 1] = msb; 
-                    self.memory[self.sp - 2] = lsb;
+        Ok(self.cycles - start)
+    }
 
-                    let addr = (((self.memory[self.pc + 2] as u16) << 8) | 
-                                (self.memory[self.pc + 1] as u16)) as usize;
+    fn region_wait_states(&self, status: u8, addr: u16) -> u8 {
+        if status & (STATUS_INP | STATUS_OUT) != 0 {
+            self.port_wait_states[(addr & 0xff) as usize]
+        } else if status & STATUS_INTA != 0 {
+            0
+        } else {
+            self.memory_wait_states
+                .iter()
+                .rev()
+                .find(|(range, _)| range.contains(&addr))
+                .map_or(0, |&(_, wait_states)| wait_states)
+        }
+    }
 
-                    self.pc = addr;
-                    self.sp -= 2;
-                }
-                0xCE => {
-                    // INSTRUCTION: ACI
-                    let result = (self.regs.a as u16) + (self.memory[self.pc + 1] as u16 + 
-                                                            self.flags.carry as u16);
-                    
-                    self.flags.carry = (result > 0xff) as u8;
-                    self.flags.zero = (((result as u8) & 0xff) == 0) as u8;
-                    self.flags.sign = (((result as u8) & 0x80) != 0) as u8;
-                    self.flags.parity = parity(result);
-
-                    self.regs.a = result as u8;
-                    self.pc += 2;
-                }
-                0xCF => { rst(self, 1); }
+    pub fn step(&mut self) -> Result<Step, StopReason> {
+        // Execute exactly one instruction and report what ran. A halted CPU
+        // executes nothing until an interrupt arrives. Scheduled events that
+        // have come due run first.
+        self.service_events();
+        let interrupt = self.interrupt_ready();
+        if self.halted && !interrupt {
+            // A halted CPU still grants the buses.
+            self.stalled = 0;
+            self.held = 0;
+            self.service_hold();
+            self.count_stalls();
+            return Err(StopReason::Halted);
+        }
 
+        let pc = self.pc;
+        if let Some([opcode, ..]) = self.interrupt_request.filter(|_| interrupt) {
+            // Trap an undocumented opcode on the data bus before the
+            // interrupt is accepted, so that it stays pending and INTE and
+            // the HALT state are left alone.
+            if self.traps(opcode) {
+                return Err(StopReason::UndocumentedOpcode { pc, opcode });
+            }
+        }
 
-                0xD0 => {
-                    // INSTRUCTION: RNC
-                    if self.flags.carry == 0 {
-                        let lsb = self.memory[self.sp];
-                        let msb = self.memory[self.sp + 1];
+        let mut bytes = [0_u8; 3];
+        let length;
+        self.m_cycle = 0;
+        self.stalled = 0;
+        self.held = 0;
+        if interrupt {
+            // Accepting the interrupt resets INTE and leaves the HALT state.
+            // The instruction comes off the data bus during INTA instead of
+            // from memory, so the PC is not advanced past it.
+            let status = if self.halted { INTERRUPT_ACKNOWLEDGE_WHILE_HALT } else { INTERRUPT_ACKNOWLEDGE };
+            self.int_enable = 0;
+            self.halted = false;
+
+            bytes = self.interrupt_request.take().unwrap();
+            length = instruction_length(bytes[0]);
+            self.machine_cycle(status, pc, bytes[0]);
+            for byte in bytes.iter().take(length).skip(1) {
+                self.machine_cycle(INTERRUPT_OPERAND_READ, pc, *byte);
+            }
+        } else {
+            bytes[0] = self.bus_read(pc, INSTRUCTION_FETCH);
+            length = instruction_length(bytes[0]);
+            for (i, byte) in bytes.iter_mut().enumerate().take(length).skip(1) {
+                *byte = self.read_byte(pc.wrapping_add(i as u16));
+            }
 
-                        let addr = (((msb as u16) << 8) | (lsb as u16)) as usize;
-                        self.pc = addr;
-                        self.sp += 2;
-                    } else {
-                        self.pc += 1;
-                    }
-                }
-                0xD1 => { pop(self, 'D'); self.pc += 1; }
-                0xD2 => {
-                    // INSTRUCTION: JNC
-                    if self.flags.carry == 0 {
-                        let addr = (((self.memory[self.pc + 2] as u16) << 8) | 
-                                    (self.memory[self.pc + 1] as u16)) as usize;
-
-                        self.pc = addr;
-                    } else {
-                        self.pc += 3;
-                    }
-                }
-                0xD3 => { 
-                    // INSTRUCTION: OUT exp
-                    // DESCRIPTION:
-                    //      The contents of the accumulator are sent to output 
-                    //      device number exp
+            self.pc = pc.wrapping_add(length as u16);
+        }
+        if let Some(e) = self.fault.take() {
+            self.pc = pc;
+            self.count_stalls();
+            return Err(StopReason::Fault(e));
+        }
 
-                    self.pc += 1; 
-                }
-                0xD4 => {
-                    // INSTRUCTION: CNC
-                    if self.flags.carry == 0 {
-                        let next_instr_addr = self.pc + 3;
-                        let msb = ((next_instr_addr & 0xff00) >> 8) as u8;
-                        let lsb = (next_instr_addr & 0x00ff) as u8;
-
-                        self.memory[self.sp - 1] = msb; 
-                        self.memory[self.sp - 2] = lsb;
-
-                        let addr = (((self.memory[self.pc + 2] as u16) << 8) | 
-                                    (self.memory[self.pc + 1] as u16)) as usize;
-
-                        self.pc = addr;
-                        self.sp -= 2;
-                    } else {
-                        self.pc += 3;
-                    }
-                }
-                0xD5 => { push(self, 'D'); self.pc += 1; }
-                0xD6 => {
-                    // INSTRUCTION: SUI
-                    let result = (self.regs.a as u16) - (self.memory[self.pc + 1] as u16);
-                    
-                    self.flags.carry = (result > 0xff) as u8;
-                    self.flags.zero = (((result as u8) & 0xff) == 0) as u8;
-                    self.flags.sign = (((result as u8) & 0x80) != 0) as u8;
-                    self.flags.parity = parity(result);
-
-                    self.regs.a = result as u8;
-                    self.pc += 2;
-                }
-                0xD7 => { rst(self, 2); }
-                0xD8 => {
-                    // INSTRUCTION: RC
-                    if self.flags.carry == 1 {
-                        let lsb = self.memory[self.sp];
-                        let msb = self.memory[self.sp + 1];
-
-                        let addr = (((msb as u16) << 8) | (lsb as u16)) as usize;
-                        self.pc = addr;
-                        self.sp += 2;
-                    } else { self.pc += 1; }
-                }
-                0xD9 => { self.pc += 1; }
-                0xDA => {
-                    // INSTRUCTION: JC
-                    if self.flags.carry == 1 {
-                        let addr = (((self.memory[self.pc + 2] as u16) << 8) | 
-                                    (self.memory[self.pc + 1] as u16)) as usize;
-
-                        self.pc = addr;
-                    } else {
-                        self.pc += 3;
-                    }
-                }
-                0xDB => { 
-                    // INSTRUCTION: IN exp
-                    // DESCRIPTION:
-                    //      An eight-bit data byte is read from input device 
-                    //     number exp and replaces the contents of the accumulator
+        let opcode = bytes[0];
+        if self.traps(opcode) {
+            self.pc = pc;
+            self.count_stalls();
+            return Err(StopReason::UndocumentedOpcode { pc, opcode });
+        }
 
-                    self.pc += 1; 
-                }
-                0xDC => {
-                    // INSTRUCTION: CC
-                    if self.flags.carry == 1 {
-                        let next_instr_addr = self.pc + 3;
-                        let msb = ((next_instr_addr & 0xff00) >> 8) as u8;
-                        let lsb = (next_instr_addr & 0x00ff) as u8;
-
-                        self.memory[self.sp - 1] = msb; 
-                        self.memory[self.sp - 2] = lsb;
-
-                        let addr = (((self.memory[self.pc + 2] as u16) << 8) | 
-                                    (self.memory[self.pc + 1] as u16)) as usize;
-
-                        self.pc = addr;
-                        self.sp -= 2;
-                    } else {
-                        self.pc += 3;
-                    }
-                }
-                0xDD => { self.pc += 1; }
-                0xDE => {
-                    // INSTRUCTION: SBI
-                    let result = (self.regs.a as u16) - (self.memory[self.pc + 1] as u16 + 
-                                                            self.flags.carry as u16);
-                    
-                    self.flags.carry = (result > 0xff) as u8;
-                    self.flags.zero = (((result as u8) & 0xff) == 0) as u8;
-                    self.flags.sign = (((result as u8) & 0x80) != 0) as u8;
-                    self.flags.parity = parity(result);
-
-                    self.regs.a = result as u8;
-                    self.pc += 2;
-                }
-                0xDF => { rst(self, 3); }
+        let (instruction, _) = decode(&bytes[..length]);
 
+        let sp = self.sp;
+        let branch_taken = self.execute(instruction);
+        self.ei_delay = instruction == Instruction::Ei;
 
-                0xE0 => {
-                    // INSTRUCTION: RPO
-                    if self.flags.parity == 0 {
-                        let lsb = self.memory[self.sp];
-                        let msb = self.memory[self.sp + 1];
+        if let Some(e) = self.fault.take() {
+            self.pc = pc;
+            self.sp = sp;
+            self.count_stalls();
+            return Err(StopReason::Fault(e));
+        }
 
-                        let addr = (((msb as u16) << 8) | (lsb as u16)) as usize;
-                        self.pc = addr;
-                        self.sp += 2;
-                    } else {
-                        self.pc += 1;
-                    }
-                }
-                0xE1 => { pop(self, 'H'); self.pc += 1; }
-                0xE2 => {
-                    // INSTRUCTION: JPO
-                    if self.flags.parity == 0 {
-                        let addr = (((self.memory[self.pc + 2] as u16) << 8) | 
-                                    (self.memory[self.pc + 1] as u16)) as usize;
-
-                        self.pc = addr;
-                    } else {
-                        self.pc += 3;
-                    }
-                }
-                0xE3 => {
-                    // INSTRUCTION: XTHL
-                    let lsb = self.memory[self.sp];
-                    let msb = self.memory[self.sp + 1];
-                    self.sp += 2;
+        let info = &OPCODES[opcode as usize];
+        let cycles = if branch_taken { info.cycles_taken } else { info.cycles };
+        let (wait_states, held_cycles) = self.count_stalls();
+        self.cycles += cycles as u64;
 
-                    self.memory[self.sp - 1] = self.regs.l;
-                    self.memory[self.sp - 2] = self.regs.h;
-                    self.sp -= 2;
+        Ok(Step { pc, opcode, instruction, length, branch_taken, cycles, wait_states, held_cycles, interrupt })
+    }
 
-                    self.regs.l = lsb;
-                    self.regs.h = msb;
+    fn traps(&self, opcode: u8) -> bool {
+        self.undocumented == UndocumentedOpcodes::Trap && is_undocumented(opcode)
+    }
 
-                    self.pc += 1;
-                }
-                0xE4 => {
-                    // INSTRUCTION: CPO
-                    if self.flags.parity == 0 {
-                        self.pc += 3; // Address of the next instruction
-                        let msb = ((self.pc & 0xff00) >> 8) as u8;
-                        let lsb = (self.pc & 0x00ff) as u8;
-
-                        self.memory[self.sp - 1] = lsb; 
-                        self.memory[self.sp - 2] = msb;
-
-                        let addr = (((self.memory[self.pc + 2] as u16) << 8) | 
-                                    (self.memory[self.pc + 1] as u16)) as usize;
-
-                        self.pc = addr;
-                        self.sp -= 2;
-                    } else {
-                        self.pc += 3;
-                    }
-                }
-                0xE5 => { push(self, 'H'); self.pc += 1; }
-                0xE6 => {
-                    // INSTRUCTION: ANI
-                    let result = (self.regs.a as u16) & (self.memory[self.pc + 1] as u16);
-                    
-                    self.flags.carry = (result > 0xff) as u8;
-                    self.flags.zero = (((result as u8) & 0xff) == 0) as u8;
-                    self.flags.sign = (((result as u8) & 0x80) != 0) as u8;
-                    self.flags.parity = parity(result);
-
-                    self.regs.a = result as u8;
-                    self.pc += 2;
-                }
-                0xE7 => { rst(self, 4); }
-                0xE8 => {
-                    // INSTRUCTION: RPE
-                    if self.flags.parity == 1 {
-                        let lsb = self.memory[self.sp];
-                        let msb = self.memory[self.sp + 1];
-
-                        let addr = (((msb as u16) << 8) | (lsb as u16)) as usize;
-                        self.pc = addr;
-                        self.sp += 2;
-                    } else {
-                        self.pc += 1;
-                    }
-                }
-                0xE9 => {
-                    // INSTRUCTION: PCHL
-                    let addr = (((self.regs.h as u16) << 8) | (self.regs.l as u16)) as usize;
-                    self.pc = addr;
-                }
-                0xEA => {
-                    // INSTRUCTION: JPE
-                    if self.flags.parity == 1 {
-                        let addr = (((self.memory[self.pc + 2] as u16) << 8) | 
-                                    (self.memory[self.pc + 1] as u16)) as usize;
-
-                        self.pc = addr;
-                    } else {
-                        self.pc += 3;
-                    }
-                }
-                0xEB => {
-                    // INSTRUCTION: XCHG
-                    let (d, e) = (self.regs.d, self.regs.d);
+    fn execute(&mut self, instruction: Instruction) -> bool {
+        // Carry out a decoded instruction. The PC already points at the next
+        // sequential instruction. Returns whether a branch was taken.
+        match instruction {
+            Instruction::Nop => {}
+            Instruction::Lxi(pair, data) => { lxi(self, pair, data); }
+            Instruction::Stax(pair) => { stax(self, pair); }
+            Instruction::Ldax(pair) => { ldax(self, pair); }
+            Instruction::Shld(mut addr) => {
+                // INSTRUCTION: SHLD
+                // DESCRIPTION: 
+                //      The contents of the L register are stored at the memory address 
+                //      formed by concatenati ng HI AD 0 with LOW ADO. The contents of 
+                //      the H register are stored at the next higher memory address.
+
+                self.write_byte(addr, self.regs.l); addr = addr.wrapping_add(1);
+                self.write_byte(addr, self.regs.h);
+            }
+            Instruction::Lhld(mut addr) => {
+                // INSTRUCTION: LHLD
+                // DESCRIPTION: 
+                //      The byte at the memory address formed by concatenating HI ADD 
+                //      with LOW ADD replaces the contents of the L register. The byte 
+                //      at the next higher memory address replaces the contents of the 
+                //      H register.
+
+                self.regs.l = self.read_byte(addr); addr = addr.wrapping_add(1);
+                self.regs.h = self.read_byte(addr);
+            }
+            Instruction::Sta(addr) => {
+                // INSTRUCTION: STA
+                // DESCRIPTION: 
+                //      The contents of the accumulator replace the byte at the memory 
+                //      address formed by concatenating HI ADD with LOW ADD.
 
-                    self.regs.d = self.regs.h;
-                    self.regs.e = self.regs.l;
+                self.write_byte(addr, self.regs.a);
+            }
+            Instruction::Lda(addr) => {
+                // INSTRUCTION: LDA
+                // DESCRIPTION: 
+                //      LDA load~ the accumulator with a copy of the byte at the location 
+                //      specified In bytes two and three of the LDA instruction.
 
-                    self.regs.h = d;
-                    self.regs.l = e;
+                self.regs.a = self.read_byte(addr);
+            }
+            Instruction::Inx(pair) => { inx(self, pair); }
+            Instruction::Dcx(pair) => { dcx(self, pair); }
+            Instruction::Inr(reg) => { inr(self, reg); }
+            Instruction::Dcr(reg) => { dcr(self, reg); }
+            Instruction::Mvi(reg, data) => { mvi(self, reg, data); }
+            Instruction::Dad(pair) => { dad(self, pair); }
+            Instruction::Rlc => {
+                // INSTRUCTION: RLC
+                // DESCRIPTION:
+                //      The contents of the accumulator are rotated one bit position to 
+                //      the left, with the high-order bit being transferred to the 
+                //      low-order bit position of the accumulator.
+
+                // compute carry and use it to compute the new value to
+                // be assigned to the accumulator (A) register.
+                let carry = (self.regs.a & 0x80) >> 7;
+                self.regs.a = (self.regs.a << 1) | carry;
+
+                // The Carry bit is set equal to the high-order bit of the accumulator.
+                self.flags.carry = carry;
+            }
+            Instruction::Rrc => {
+                // INSTRUCTION: RRC
+                // DESCRIPTION:
+                //      The contents of the accumulator are rotated one bit position to 
+                //      the right, with the low-order bit being transferred to the 
+                //      high-order bit position of the accumulator.
+
+                // compute carry and use it to compute the new value to
+                // be assigned to the accumulator (A) register.
+                let carry = self.regs.a & 0x01;
+                self.regs.a = (self.regs.a >> 1) | (carry << 7);
+
+                // The Carry bit is set equal to the high-order bit of the accumulator.
+                self.flags.carry = carry;
+            }
+            Instruction::Ral => {
+                // INSTRUCTION: RAL
+                // DESCRIPTION: 
+                //      The contents of the accumulator are rotated one bit position to the left.
+                //      The high-order bit of the accumulator replaces the Carry bit, while the 
+                //      Carry bit replaces the high-order bit of the accumulator.
+                
+                let temp = self.flags.carry;
+                let carry = (self.regs.a & 0x80) >> 7;
+                self.regs.a = (self.regs.a << 1) | (temp << 7);
 
-                    self.pc += 1;
-                }
-                0xEC => {
-                    // INSTRUCTION: CPE
-                    if self.flags.parity == 1 {
-                        self.pc += 3; // Address of the next instruction
-                        let msb = ((self.pc & 0xff00) >> 8) as u8;
-                        let lsb = (self.pc & 0x00ff) as u8;
-
-                        self.memory[self.sp - 1] = lsb; 
-                        self.memory[self.sp - 2] = msb;
-
-                        let addr = (((self.memory[self.pc + 2] as u16) << 8) | 
-                                    (self.memory[self.pc + 1] as u16)) as usize;
-
-                        self.pc = addr;
-                        self.sp -= 2;
-                    } else {
-                        self.pc += 3;
-                    }
+                // The Carry bit is set equal to the high-order bit of the accumulator.
+                self.flags.carry = carry;
+            }
+            Instruction::Rar => {
+                // INSTRUCTION: RAR
+                // DESCRIPTION: 
+                //      The contents of the accumulator are rotated one bit position 
+                //      to the right. The low-order bit of the accumulator replaces the
+                //      carry bit, while the carry bit replaces the high-order bit of
+                //      the accumulator.
+
+                let temp = self.flags.carry;
+                let carry = self.regs.a & 0x01;
+                self.regs.a = (self.regs.a >> 1) | (temp << 7);
+
+                // The Carry bit is set equal to the high-order bit of the accumulator.
+                self.flags.carry = carry;
+            }
+            Instruction::Daa => {
+                // INSTRUCTION: DAA
+                // DESCRIPTION:
+                //      The DAA intruction adjusts the eight-bit value in the accumulator 
+                //      to form two four-bit binary coded decimal digits.
+
+                let mut correction = 0;
+                if (self.regs.a & 0x0f) > 9 || self.flags.aux_carry == 1 {
+                    correction |= 0x06;
                 }
-                0xED => { self.pc += 1; }
-                0xEE => {
-                    // INSTRUCTION: XRI
-                    let result = (self.regs.a as u16) ^ (self.memory[self.pc + 1] as u16);
-                    
-                    self.flags.carry = (result > 0xff) as u8;
-                    self.flags.zero = (((result as u8) & 0xff) == 0) as u8;
-                    self.flags.sign = (((result as u8) & 0x80) != 0) as u8;
-                    self.flags.parity = parity(result);
-
-                    self.regs.a = result as u8;
-                    self.pc += 2;
+                if self.regs.a > 0x99 || self.flags.carry == 1 {
+                    correction |= 0x60;
+                    self.flags.carry = 1;
                 }
-                0xEF => { rst(self, 5); }
 
+                self.flags.aux_carry = half_carry(self.regs.a, correction, 0);
+                self.regs.a = self.regs.a.wrapping_add(correction);
 
-                0xF0 => {
-                    // INSTRUCTION: RP
-                    if self.flags.sign == 0 {
-                        let lsb = self.memory[self.sp];
-                        let msb = self.memory[self.sp + 1];
+                self.flags.zero = (self.regs.a == 0) as u8;
+                self.flags.sign = ((self.regs.a & 0x80) != 0) as u8;
+                self.flags.parity = parity(self.regs.a as u16);
+            }
+            Instruction::Cma => {
+                // INSTRUCTION: CMA
+                // DESCRIPTION: 
+                //      Each bit of the contents of the accumulator is complemented 
+                //      (producing the one's complement). 
 
-                        let addr = (((msb as u16) << 8) | (lsb as u16)) as usize;
-                        self.pc = addr;
-                        self.sp += 2;
-                    } else {
-                        self.pc += 1;
-                    }
-                }
-                0xF1 => { pop(self, 'P'); self.pc += 1; }
-                0xF2 => {
-                    // INSTRUCTION: JP
-                    if self.flags.sign == 1 {
-                        let addr = (((self.memory[self.pc + 2] as u16) << 8) | 
-                                    (self.memory[self.pc + 1] as u16)) as usize;
-
-                        self.pc = addr;
-                    } else {
-                        self.pc += 3;
-                    }
-                }
-                0xF3 => {
-                    // INSTRUCTION: DI
+                self.regs.a = !self.regs.a;
+            }
+            Instruction::Stc => { self.flags.carry = 1; }
+            Instruction::Cmc => { self.flags.carry ^= 1; }
+
+            Instruction::Mov(dst, src) => {
+                // INSTRUCTION: MOV dst, src
+                // DESCRIPTION: copy a register, or the memory byte at HL, into another
+                let value = self.get(src);
+                self.set(dst, value);
+            }
+            Instruction::Hlt => {
+                // INSTRUCTION: HLT
+                // DESCRIPTION:
+                //      The program counter is incremented to the address of the
+                //      next sequential instruction and the CPU then enters the
+                //      HALT state, which only an interrupt (or reset) leaves.
 
-                    // disable interrupts
-                    self.int_enable = 0;
-                    self.pc += 1;
+                self.halted = true;
+
+                // The bus floats during the halt acknowledge cycle.
+                self.machine_cycle(HALT_ACKNOWLEDGE, self.pc, 0xff);
+            }
+
+            Instruction::Add(reg) => { let value = self.get(reg); add_to_accu(self, value); }
+            Instruction::Adc(reg) => { let value = self.get(reg); adc(self, value); }
+            Instruction::Sub(reg) => { let value = self.get(reg); sub_accu(self, value); }
+            Instruction::Sbb(reg) => { let value = self.get(reg); sbb(self, value); }
+            Instruction::Ana(reg) => { let value = self.get(reg); ana(self, value); }
+            Instruction::Xra(reg) => { let value = self.get(reg); xra(self, value); }
+            Instruction::Ora(reg) => { let value = self.get(reg); ora(self, value); }
+            Instruction::Cmp(reg) => { let value = self.get(reg); cmp(self, value); }
+
+            Instruction::Adi(data) => { add_to_accu(self, data); }
+            Instruction::Aci(data) => { adc(self, data); }
+            Instruction::Sui(data) => { sub_accu(self, data); }
+            Instruction::Sbi(data) => { sbb(self, data); }
+            Instruction::Ani(data) => { ana(self, data); }
+            Instruction::Xri(data) => { xra(self, data); }
+            Instruction::Ori(data) => { ora(self, data); }
+            Instruction::Cpi(data) => { cmp(self, data); }
+
+            Instruction::Jmp(addr) => {
+                // INSTRUCTION: JMP
+                self.pc = addr;
+                return true;
+            }
+            Instruction::Jcc(condition, addr) => {
+                // INSTRUCTION: JNZ, JZ, JNC, JC, JPO, JPE, JP, JM
+                let taken = self.flags.satisfies(condition);
+                if taken {
+                    self.pc = addr;
                 }
-                0xF4 => {
-                    // INSTRUCTION: CP
-                    if self.flags.sign == 0 {
-                        self.pc += 3; // Address of the next instruction
-                        let msb = ((self.pc & 0xff00) >> 8) as u8;
-                        let lsb = (self.pc & 0x00ff) as u8;
-
-                        self.memory[self.sp - 1] = lsb;
-                        self.memory[self.sp - 2] = msb;
-
-                        let addr = (((self.memory[self.pc + 2] as u16) << 8) | 
-                                    (self.memory[self.pc + 1] as u16)) as usize;
-
-                        self.pc = addr;
-                        self.sp += 2;
-                    } else {
-                        self.pc += 3;
-                    }
-                }   
-                0xF5 => { push(self, 'P'); self.pc += 1; }
-                0xF6 => {
-                    // INSTRUCTION: ORI
-                    let result = (self.regs.a as u16) | (self.memory[self.pc + 1] as u16);
-                    
-                    self.flags.carry = (result > 0xff) as u8;
-                    self.flags.zero = (((result as u8) & 0xff) == 0) as u8;
-                    self.flags.sign = (((result as u8) & 0x80) != 0) as u8;
-                    self.flags.parity = parity(result);
-
-                    self.regs.a = result as u8;
-                    self.pc += 2;
+                return taken;
+            }
+            Instruction::Call(addr) => {
+                // INSTRUCTION: CALL
+                self.call(addr);
+                return true;
+            }
+            Instruction::Ccc(condition, addr) => {
+                // INSTRUCTION: CNZ, CZ, CNC, CC, CPO, CPE, CP, CM
+                let taken = self.flags.satisfies(condition);
+                if taken {
+                    self.call(addr);
                 }
-                0xF7 => { rst(self, 6); }
-                0xF8 => {
-                    // INSTRUCTION: RM
-                    if self.flags.sign == 1 {
-                        let lsb = self.memory[self.sp];
-                        let msb = self.memory[self.sp + 1];
-
-                        let addr = (((msb as u16) << 8) | (lsb as u16)) as usize;
-                        self.pc = addr;
-                        self.sp += 2;
-                    } else {
-                        self.pc += 1;
-                    }
+                return taken;
+            }
+            Instruction::Ret => {
+                // INSTRUCTION: RET
+                self.ret();
+                return true;
+            }
+            Instruction::Rcc(condition) => {
+                // INSTRUCTION: RNZ, RZ, RNC, RC, RPO, RPE, RP, RM
+                let taken = self.flags.satisfies(condition);
+                if taken {
+                    self.ret();
                 }
-                0xF9 => {
-                    // INSTRUCTION: SPHL
-                    let addr = (((self.regs.h as u16) << 8) | (self.regs.l as u16)) as usize;
-                    self.sp = addr;
+                return taken;
+            }
+            Instruction::Rst(code) => {
+                rst(self, code);
+                return true;
+            }
+            Instruction::Pchl => {
+                // INSTRUCTION: PCHL
+                self.pc = self.pair(RegPair::HL);
+                return true;
+            }
 
-                    self.pc += 1;
-                }
-                0xFA => {
-                    // INSTRUCTION: JM
-                    if self.flags.sign == 1 {
-                        let addr = (((self.memory[self.pc + 2] as u16) << 8) | 
-                                    (self.memory[self.pc + 1] as u16)) as usize;
-
-                        self.pc = addr;
-                    } else {
-                        self.pc += 3;
-                    }
-                }
-                0xFB => {
-                    // INSTRUCTION: EI
+            Instruction::Push(pair) => { push(self, pair); }
+            Instruction::Pop(pair) => { pop(self, pair); }
+            Instruction::Xthl => {
+                // INSTRUCTION: XTHL
+                // DESCRIPTION:
+                //      The contents of the L register are exchanged with the contents 
+                //      of the memory byte whose address is held in the stack pointer SP. 
+                //      The contents of the H register are exchanged with the contents 
+                //      of the memory byte whose address is one greater than that held 
+                //      in the stack pointer. 
+
+                let lsb = self.read_stack(self.sp);
+                let msb = self.read_stack(self.sp.wrapping_add(1));
+
+                // The 8080 writes H back first.
+                self.write_stack(self.sp.wrapping_add(1), self.regs.h);
+                self.write_stack(self.sp, self.regs.l);
+
+                self.regs.l = lsb;
+                self.regs.h = msb;
+            }
+            Instruction::Sphl => {
+                // INSTRUCTION: SPHL
+                self.sp = self.pair(RegPair::HL);
+            }
+            Instruction::Xchg => {
+                // INSTRUCTION: XCHG
+                let de = self.pair(RegPair::DE);
 
-                    // enable interrupts
-                    self.int_enable = 1;
+                self.set_pair(RegPair::DE, self.pair(RegPair::HL));
+                self.set_pair(RegPair::HL, de);
+            }
 
-                    self.pc += 1;
-                }
-                0xFC => {
-                    // INSTRUCTION: CM
-                    if self.flags.sign == 1 {
-                        self.pc += 3; // Address of the next instruction
-                        let msb = ((self.pc & 0xff00) >> 8) as u8;
-                        let lsb = (self.pc & 0x00ff) as u8;
-
-                        self.memory[self.sp - 1] = lsb;
-                        self.memory[self.sp - 2] = msb;
-
-                        let addr = (((self.memory[self.pc + 2] as u16) << 8) | 
-                                    (self.memory[self.pc + 1] as u16)) as usize;
-
-                        self.pc = addr;
-                        self.sp += 2;
-                    } else {
-                        self.pc += 3;
-                    }
-                }
-                0xFD => { self.pc += 1; }
-                0xFE => {
-                    // INSTRUCTION: CPI
-                    let result = (self.regs.a as i16) - (self.memory[self.pc + 1] as i16);
-                    
-                    self.flags.carry = (self.regs.a < self.memory[self.pc + 1]) as u8;
-                    self.flags.zero = (result == 0) as u8;
-                    self.flags.sign = (((result as u8) & 0x80) != 0) as u8;
-                    self.flags.parity = parity(result as u16);
-
-                    self.pc += 2;
-                }
-                0xFF => { rst(self, 7); }
+            Instruction::Out(port) => { 
+                // INSTRUCTION: OUT exp
+                // DESCRIPTION:
+                //      The contents of the accumulator are sent to output 
+                //      device number exp
+
+                self.io.output(port, self.regs.a);
+                self.machine_cycle(OUTPUT_WRITE, port_address(port), self.regs.a);
+            }
+            Instruction::In(port) => { 
+                // INSTRUCTION: IN exp
+                // DESCRIPTION:
+                //      An eight-bit data byte is read from input device 
+                //     number exp and replaces the contents of the accumulator
+
+                self.regs.a = self.io.input(port);
+                self.machine_cycle(INPUT_READ, port_address(port), self.regs.a);
+            }
+            Instruction::Ei => {
+                // INSTRUCTION: EI
+
+                // enable interrupts
+                self.int_enable = 1;
+            }
+            Instruction::Di => {
+                // INSTRUCTION: DI
+
+                // disable interrupts
+                self.int_enable = 0;
             }
         }
+
+        false
+    }
+
+    fn call(&mut self, addr: u16) {
+        // Push the address of the next instruction (or of the interrupted
+        // one, for a CALL supplied during INTA), high byte first so that it
+        // ends up above the low byte, and jump to `addr`.
+        let next_instr_addr = self.pc;
+        let msb = ((next_instr_addr & 0xff00) >> 8) as u8;
+        let lsb = (next_instr_addr & 0x00ff) as u8;
+
+        self.write_stack(self.sp.wrapping_sub(1), msb);
+        self.write_stack(self.sp.wrapping_sub(2), lsb);
+
+        self.pc = addr;
+        self.sp = self.sp.wrapping_sub(2);
+    }
+
+    fn ret(&mut self) {
+        // Pop the return address pushed by CALL and jump to it.
+        let lsb = self.read_stack(self.sp);
+        let msb = self.read_stack(self.sp.wrapping_add(1));
+
+        self.pc = ((msb as u16) << 8) | (lsb as u16);
+        self.sp = self.sp.wrapping_add(2);
+    }
+
+    // Like `Register::get` and `Register::set`, but M reaches the memory
+    // byte addressed by HL.
+    pub fn get(&mut self, reg: Reg8) -> u8 {
+        match self.regs.get(reg) {
+            Some(value) => value,
+            None => self.read_byte(self.pair(RegPair::HL))
+        }
+    }
+
+    pub fn set(&mut self, reg: Reg8, value: u8) {
+        if !self.regs.set(reg, value) {
+            self.write_byte(self.pair(RegPair::HL), value);
+        }
     }
-}
\ No newline at end of file
+
+    // Like `Register::pair` and `Register::set_pair`, but covering SP and
+    // PSW (A above the flag byte) as well.
+    pub fn pair(&self, pair: RegPair) -> u16 {
+        match (pair, self.regs.pair(pair)) {
+            (_, Some(value)) => value,
+            (RegPair::SP, None) => self.sp,
+            _ => ((self.regs.a as u16) << 8) | (self.flags.pack() as u16)
+        }
+    }
+
+    pub fn set_pair(&mut self, pair: RegPair, value: u16) {
+        if self.regs.set_pair(pair, value) {
+            return;
+        }
+
+        if pair == RegPair::SP {
+            self.sp = value;
+        } else {
+            self.regs.a = (value >> 8) as u8;
+            self.flags = ConditionFlags::unpack(value as u8);
+        }
+    }
+}
+
+// Spread `seed` over all 64 bits (one round of splitmix64), so that nearby
+// seeds start xorshift64 from unrelated states. Zero, which xorshift64 never
+// leaves, is swapped for a fixed constant.
+fn mix(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+
+    if z == 0 { 0x2545_f491_4f6c_dd1d } else { z }
+}
+
+// During IN and OUT the port number is placed on both halves of the address
+// bus.
+fn port_address(port: u8) -> u16 {
+    ((port as u16) << 8) | (port as u16)
+}
+
+impl Default for Intel8080 {
+    fn default() -> Self {
+        Intel8080::new()
+    }
+}
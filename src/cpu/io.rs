@@ -0,0 +1,19 @@
+// The 8080's 256 I/O ports, as seen by the IN and OUT instructions. A
+// machine wires its serial cards, shift registers and switches in by
+// implementing this trait and attaching it with `Intel8080::attach_io`.
+pub trait IoDevice {
+    fn input(&mut self, port: u8) -> u8;
+    fn output(&mut self, port: u8, value: u8);
+}
+
+// What the CPU sees when nothing is attached: reads float high and writes
+// go nowhere.
+pub struct Unconnected;
+
+impl IoDevice for Unconnected {
+    fn input(&mut self, _port: u8) -> u8 {
+        0xff
+    }
+
+    fn output(&mut self, _port: u8, _value: u8) {}
+}
@@ -1,5 +1,12 @@
+pub mod bus;
+pub mod clock;
+pub mod decoder;
+pub mod error;
 pub mod instructions;
 pub mod intel8080;
+pub mod io;
+pub mod opcodes;
+pub mod scheduler;
 pub mod utils {
     pub fn parity(mut result: u16) -> u8
     {
@@ -12,6 +19,41 @@ pub mod utils {
 
         ((counter & 0x01) == 0) as u8
     }
+
+    // Carry out of bit 3 when adding `a`, `b` and `carry`, which is what the
+    // auxiliary carry flag records. Subtraction is done by the 8080 as an
+    // addition of the complement, so it goes through here as
+    // `half_carry(a, !b, 1 - borrow)`.
+    pub fn half_carry(a: u8, b: u8, carry: u8) -> u8 {
+        (((a & 0x0f) + (b & 0x0f) + carry) > 0x0f) as u8
+    }
+}
+
+use crate::cpu::decoder::Condition;
+
+// The eight-bit operands of the instruction set, in the order of their
+// encoding. M is the memory byte addressed by HL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reg8 {
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    M,
+    A
+}
+
+// The sixteen-bit operands. SP lives in `Intel8080::sp`, and PSW (A and the
+// flags) only appears in PUSH and POP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegPair {
+    BC,
+    DE,
+    HL,
+    SP,
+    PSW
 }
 
 pub struct ConditionFlags {
@@ -32,6 +74,44 @@ impl ConditionFlags {
             sign: 0_u8
         }
     }
+
+    // The flags as the 8080 stores them in the low byte of PSW:
+    //
+    //      bit:  7  6  5  4  3  2  1  0
+    //            S  Z  0  AC 0  P  1  CY
+    pub fn pack(&self) -> u8 {
+        self.sign << 7 | self.zero << 6 | self.aux_carry << 4 | self.parity << 2 | 0x02 | self.carry
+    }
+
+    // Whether a conditional jump, call or return on `condition` is taken.
+    pub fn satisfies(&self, condition: Condition) -> bool {
+        match condition {
+            Condition::NotZero => self.zero == 0,
+            Condition::Zero => self.zero == 1,
+            Condition::NoCarry => self.carry == 0,
+            Condition::Carry => self.carry == 1,
+            Condition::ParityOdd => self.parity == 0,
+            Condition::ParityEven => self.parity == 1,
+            Condition::Plus => self.sign == 0,
+            Condition::Minus => self.sign == 1
+        }
+    }
+
+    pub fn unpack(psw: u8) -> ConditionFlags {
+        ConditionFlags {
+            carry: psw & 0x01,
+            aux_carry: (psw >> 4) & 0x01,
+            zero: (psw >> 6) & 0x01,
+            parity: (psw >> 2) & 0x01,
+            sign: (psw >> 7) & 0x01
+        }
+    }
+}
+
+impl Default for ConditionFlags {
+    fn default() -> Self {
+        ConditionFlags::new()
+    }
 }
 
 pub struct Register {
@@ -52,4 +132,69 @@ impl Register {
             l: 0_u8
         }
     }
+}
+
+impl Register {
+    // M, SP and PSW are not held here: the getters return None for them
+    // and the setters return false and change nothing. `Intel8080::get`,
+    // `Intel8080::set`, `Intel8080::pair` and `Intel8080::set_pair` cover
+    // every operand.
+    pub fn get(&self, reg: Reg8) -> Option<u8> {
+        match reg {
+            Reg8::B => Some(self.b),
+            Reg8::C => Some(self.c),
+            Reg8::D => Some(self.d),
+            Reg8::E => Some(self.e),
+            Reg8::H => Some(self.h),
+            Reg8::L => Some(self.l),
+            Reg8::A => Some(self.a),
+            Reg8::M => None
+        }
+    }
+
+    #[must_use]
+    pub fn set(&mut self, reg: Reg8, value: u8) -> bool {
+        match reg {
+            Reg8::B => { self.b = value; }
+            Reg8::C => { self.c = value; }
+            Reg8::D => { self.d = value; }
+            Reg8::E => { self.e = value; }
+            Reg8::H => { self.h = value; }
+            Reg8::L => { self.l = value; }
+            Reg8::A => { self.a = value; }
+            Reg8::M => return false
+        }
+
+        true
+    }
+
+    pub fn pair(&self, pair: RegPair) -> Option<u16> {
+        let (hi, lo) = match pair {
+            RegPair::BC => (self.b, self.c),
+            RegPair::DE => (self.d, self.e),
+            RegPair::HL => (self.h, self.l),
+            RegPair::SP | RegPair::PSW => return None
+        };
+
+        Some(((hi as u16) << 8) | (lo as u16))
+    }
+
+    #[must_use]
+    pub fn set_pair(&mut self, pair: RegPair, value: u16) -> bool {
+        let (hi, lo) = ((value >> 8) as u8, value as u8);
+        match pair {
+            RegPair::BC => { self.b = hi; self.c = lo; }
+            RegPair::DE => { self.d = hi; self.e = lo; }
+            RegPair::HL => { self.h = hi; self.l = lo; }
+            RegPair::SP | RegPair::PSW => return false
+        }
+
+        true
+    }
+}
+
+impl Default for Register {
+    fn default() -> Self {
+        Register::new()
+    }
 }
\ No newline at end of file
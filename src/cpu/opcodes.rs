@@ -0,0 +1,333 @@
+// Facts about each of the 256 opcodes, indexed by opcode, for anything that
+// wants to know about an instruction without decoding or running it:
+// disassemblers, tracers, profilers, coverage tools.
+//
+// Mnemonics are in Intel syntax, with d8, d16 and a16 standing for an
+// immediate byte, an immediate word and an address. Cycle counts are in
+// T-states. The undocumented opcodes are listed with the instruction they
+// behave as.
+
+// The condition flags an instruction may change, as a mask over the PSW
+// flag byte (see `ConditionFlags::pack`).
+pub const FLAG_SIGN: u8 = 0x80;
+pub const FLAG_ZERO: u8 = 0x40;
+pub const FLAG_AUX_CARRY: u8 = 0x10;
+pub const FLAG_PARITY: u8 = 0x04;
+pub const FLAG_CARRY: u8 = 0x01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpcodeInfo {
+    pub mnemonic: &'static str,
+    // Instruction length in bytes, opcode included.
+    pub length: u8,
+    // T-states taken. For a conditional CALL or RET, `cycles` is the cost
+    // when the condition fails and `cycles_taken` the cost when it holds;
+    // for every other opcode the two are the same.
+    pub cycles: u8,
+    pub cycles_taken: u8,
+    pub flags: u8,
+    // May transfer control somewhere other than the next instruction.
+    pub branch: bool,
+    // Reads or writes memory beyond fetching the instruction itself.
+    pub memory: bool,
+    pub undocumented: bool
+}
+
+const NONE: u8 = 0;
+const CY: u8 = FLAG_CARRY;
+const SZAP: u8 = FLAG_SIGN | FLAG_ZERO | FLAG_AUX_CARRY | FLAG_PARITY;
+const ALL: u8 = SZAP | FLAG_CARRY;
+
+const fn op(mnemonic: &'static str, length: u8, cycles: u8, flags: u8) -> OpcodeInfo {
+    OpcodeInfo {
+        mnemonic,
+        length,
+        cycles,
+        cycles_taken: cycles,
+        flags,
+        branch: false,
+        memory: false,
+        undocumented: false
+    }
+}
+
+impl OpcodeInfo {
+    const fn taken(mut self, cycles: u8) -> Self {
+        self.cycles_taken = cycles;
+        self
+    }
+
+    const fn branch(mut self) -> Self {
+        self.branch = true;
+        self
+    }
+
+    const fn memory(mut self) -> Self {
+        self.memory = true;
+        self
+    }
+
+    const fn undocumented(mut self) -> Self {
+        self.undocumented = true;
+        self
+    }
+}
+
+pub const OPCODES: [OpcodeInfo; 256] = [
+    op("NOP", 1, 4, NONE), // 0x00
+    op("LXI B,d16", 3, 10, NONE), // 0x01
+    op("STAX B", 1, 7, NONE).memory(), // 0x02
+    op("INX B", 1, 5, NONE), // 0x03
+    op("INR B", 1, 5, SZAP), // 0x04
+    op("DCR B", 1, 5, SZAP), // 0x05
+    op("MVI B,d8", 2, 7, NONE), // 0x06
+    op("RLC", 1, 4, CY), // 0x07
+    op("NOP", 1, 4, NONE).undocumented(), // 0x08
+    op("DAD B", 1, 10, CY), // 0x09
+    op("LDAX B", 1, 7, NONE).memory(), // 0x0A
+    op("DCX B", 1, 5, NONE), // 0x0B
+    op("INR C", 1, 5, SZAP), // 0x0C
+    op("DCR C", 1, 5, SZAP), // 0x0D
+    op("MVI C,d8", 2, 7, NONE), // 0x0E
+    op("RRC", 1, 4, CY), // 0x0F
+    op("NOP", 1, 4, NONE).undocumented(), // 0x10
+    op("LXI D,d16", 3, 10, NONE), // 0x11
+    op("STAX D", 1, 7, NONE).memory(), // 0x12
+    op("INX D", 1, 5, NONE), // 0x13
+    op("INR D", 1, 5, SZAP), // 0x14
+    op("DCR D", 1, 5, SZAP), // 0x15
+    op("MVI D,d8", 2, 7, NONE), // 0x16
+    op("RAL", 1, 4, CY), // 0x17
+    op("NOP", 1, 4, NONE).undocumented(), // 0x18
+    op("DAD D", 1, 10, CY), // 0x19
+    op("LDAX D", 1, 7, NONE).memory(), // 0x1A
+    op("DCX D", 1, 5, NONE), // 0x1B
+    op("INR E", 1, 5, SZAP), // 0x1C
+    op("DCR E", 1, 5, SZAP), // 0x1D
+    op("MVI E,d8", 2, 7, NONE), // 0x1E
+    op("RAR", 1, 4, CY), // 0x1F
+    op("NOP", 1, 4, NONE).undocumented(), // 0x20
+    op("LXI H,d16", 3, 10, NONE), // 0x21
+    op("SHLD a16", 3, 16, NONE).memory(), // 0x22
+    op("INX H", 1, 5, NONE), // 0x23
+    op("INR H", 1, 5, SZAP), // 0x24
+    op("DCR H", 1, 5, SZAP), // 0x25
+    op("MVI H,d8", 2, 7, NONE), // 0x26
+    op("DAA", 1, 4, ALL), // 0x27
+    op("NOP", 1, 4, NONE).undocumented(), // 0x28
+    op("DAD H", 1, 10, CY), // 0x29
+    op("LHLD a16", 3, 16, NONE).memory(), // 0x2A
+    op("DCX H", 1, 5, NONE), // 0x2B
+    op("INR L", 1, 5, SZAP), // 0x2C
+    op("DCR L", 1, 5, SZAP), // 0x2D
+    op("MVI L,d8", 2, 7, NONE), // 0x2E
+    op("CMA", 1, 4, NONE), // 0x2F
+    op("NOP", 1, 4, NONE).undocumented(), // 0x30
+    op("LXI SP,d16", 3, 10, NONE), // 0x31
+    op("STA a16", 3, 13, NONE).memory(), // 0x32
+    op("INX SP", 1, 5, NONE), // 0x33
+    op("INR M", 1, 10, SZAP).memory(), // 0x34
+    op("DCR M", 1, 10, SZAP).memory(), // 0x35
+    op("MVI M,d8", 2, 10, NONE).memory(), // 0x36
+    op("STC", 1, 4, CY), // 0x37
+    op("NOP", 1, 4, NONE).undocumented(), // 0x38
+    op("DAD SP", 1, 10, CY), // 0x39
+    op("LDA a16", 3, 13, NONE).memory(), // 0x3A
+    op("DCX SP", 1, 5, NONE), // 0x3B
+    op("INR A", 1, 5, SZAP), // 0x3C
+    op("DCR A", 1, 5, SZAP), // 0x3D
+    op("MVI A,d8", 2, 7, NONE), // 0x3E
+    op("CMC", 1, 4, CY), // 0x3F
+    op("MOV B,B", 1, 5, NONE), // 0x40
+    op("MOV B,C", 1, 5, NONE), // 0x41
+    op("MOV B,D", 1, 5, NONE), // 0x42
+    op("MOV B,E", 1, 5, NONE), // 0x43
+    op("MOV B,H", 1, 5, NONE), // 0x44
+    op("MOV B,L", 1, 5, NONE), // 0x45
+    op("MOV B,M", 1, 7, NONE).memory(), // 0x46
+    op("MOV B,A", 1, 5, NONE), // 0x47
+    op("MOV C,B", 1, 5, NONE), // 0x48
+    op("MOV C,C", 1, 5, NONE), // 0x49
+    op("MOV C,D", 1, 5, NONE), // 0x4A
+    op("MOV C,E", 1, 5, NONE), // 0x4B
+    op("MOV C,H", 1, 5, NONE), // 0x4C
+    op("MOV C,L", 1, 5, NONE), // 0x4D
+    op("MOV C,M", 1, 7, NONE).memory(), // 0x4E
+    op("MOV C,A", 1, 5, NONE), // 0x4F
+    op("MOV D,B", 1, 5, NONE), // 0x50
+    op("MOV D,C", 1, 5, NONE), // 0x51
+    op("MOV D,D", 1, 5, NONE), // 0x52
+    op("MOV D,E", 1, 5, NONE), // 0x53
+    op("MOV D,H", 1, 5, NONE), // 0x54
+    op("MOV D,L", 1, 5, NONE), // 0x55
+    op("MOV D,M", 1, 7, NONE).memory(), // 0x56
+    op("MOV D,A", 1, 5, NONE), // 0x57
+    op("MOV E,B", 1, 5, NONE), // 0x58
+    op("MOV E,C", 1, 5, NONE), // 0x59
+    op("MOV E,D", 1, 5, NONE), // 0x5A
+    op("MOV E,E", 1, 5, NONE), // 0x5B
+    op("MOV E,H", 1, 5, NONE), // 0x5C
+    op("MOV E,L", 1, 5, NONE), // 0x5D
+    op("MOV E,M", 1, 7, NONE).memory(), // 0x5E
+    op("MOV E,A", 1, 5, NONE), // 0x5F
+    op("MOV H,B", 1, 5, NONE), // 0x60
+    op("MOV H,C", 1, 5, NONE), // 0x61
+    op("MOV H,D", 1, 5, NONE), // 0x62
+    op("MOV H,E", 1, 5, NONE), // 0x63
+    op("MOV H,H", 1, 5, NONE), // 0x64
+    op("MOV H,L", 1, 5, NONE), // 0x65
+    op("MOV H,M", 1, 7, NONE).memory(), // 0x66
+    op("MOV H,A", 1, 5, NONE), // 0x67
+    op("MOV L,B", 1, 5, NONE), // 0x68
+    op("MOV L,C", 1, 5, NONE), // 0x69
+    op("MOV L,D", 1, 5, NONE), // 0x6A
+    op("MOV L,E", 1, 5, NONE), // 0x6B
+    op("MOV L,H", 1, 5, NONE), // 0x6C
+    op("MOV L,L", 1, 5, NONE), // 0x6D
+    op("MOV L,M", 1, 7, NONE).memory(), // 0x6E
+    op("MOV L,A", 1, 5, NONE), // 0x6F
+    op("MOV M,B", 1, 7, NONE).memory(), // 0x70
+    op("MOV M,C", 1, 7, NONE).memory(), // 0x71
+    op("MOV M,D", 1, 7, NONE).memory(), // 0x72
+    op("MOV M,E", 1, 7, NONE).memory(), // 0x73
+    op("MOV M,H", 1, 7, NONE).memory(), // 0x74
+    op("MOV M,L", 1, 7, NONE).memory(), // 0x75
+    op("HLT", 1, 7, NONE), // 0x76
+    op("MOV M,A", 1, 7, NONE).memory(), // 0x77
+    op("MOV A,B", 1, 5, NONE), // 0x78
+    op("MOV A,C", 1, 5, NONE), // 0x79
+    op("MOV A,D", 1, 5, NONE), // 0x7A
+    op("MOV A,E", 1, 5, NONE), // 0x7B
+    op("MOV A,H", 1, 5, NONE), // 0x7C
+    op("MOV A,L", 1, 5, NONE), // 0x7D
+    op("MOV A,M", 1, 7, NONE).memory(), // 0x7E
+    op("MOV A,A", 1, 5, NONE), // 0x7F
+    op("ADD B", 1, 4, ALL), // 0x80
+    op("ADD C", 1, 4, ALL), // 0x81
+    op("ADD D", 1, 4, ALL), // 0x82
+    op("ADD E", 1, 4, ALL), // 0x83
+    op("ADD H", 1, 4, ALL), // 0x84
+    op("ADD L", 1, 4, ALL), // 0x85
+    op("ADD M", 1, 7, ALL).memory(), // 0x86
+    op("ADD A", 1, 4, ALL), // 0x87
+    op("ADC B", 1, 4, ALL), // 0x88
+    op("ADC C", 1, 4, ALL), // 0x89
+    op("ADC D", 1, 4, ALL), // 0x8A
+    op("ADC E", 1, 4, ALL), // 0x8B
+    op("ADC H", 1, 4, ALL), // 0x8C
+    op("ADC L", 1, 4, ALL), // 0x8D
+    op("ADC M", 1, 7, ALL).memory(), // 0x8E
+    op("ADC A", 1, 4, ALL), // 0x8F
+    op("SUB B", 1, 4, ALL), // 0x90
+    op("SUB C", 1, 4, ALL), // 0x91
+    op("SUB D", 1, 4, ALL), // 0x92
+    op("SUB E", 1, 4, ALL), // 0x93
+    op("SUB H", 1, 4, ALL), // 0x94
+    op("SUB L", 1, 4, ALL), // 0x95
+    op("SUB M", 1, 7, ALL).memory(), // 0x96
+    op("SUB A", 1, 4, ALL), // 0x97
+    op("SBB B", 1, 4, ALL), // 0x98
+    op("SBB C", 1, 4, ALL), // 0x99
+    op("SBB D", 1, 4, ALL), // 0x9A
+    op("SBB E", 1, 4, ALL), // 0x9B
+    op("SBB H", 1, 4, ALL), // 0x9C
+    op("SBB L", 1, 4, ALL), // 0x9D
+    op("SBB M", 1, 7, ALL).memory(), // 0x9E
+    op("SBB A", 1, 4, ALL), // 0x9F
+    op("ANA B", 1, 4, ALL), // 0xA0
+    op("ANA C", 1, 4, ALL), // 0xA1
+    op("ANA D", 1, 4, ALL), // 0xA2
+    op("ANA E", 1, 4, ALL), // 0xA3
+    op("ANA H", 1, 4, ALL), // 0xA4
+    op("ANA L", 1, 4, ALL), // 0xA5
+    op("ANA M", 1, 7, ALL).memory(), // 0xA6
+    op("ANA A", 1, 4, ALL), // 0xA7
+    op("XRA B", 1, 4, ALL), // 0xA8
+    op("XRA C", 1, 4, ALL), // 0xA9
+    op("XRA D", 1, 4, ALL), // 0xAA
+    op("XRA E", 1, 4, ALL), // 0xAB
+    op("XRA H", 1, 4, ALL), // 0xAC
+    op("XRA L", 1, 4, ALL), // 0xAD
+    op("XRA M", 1, 7, ALL).memory(), // 0xAE
+    op("XRA A", 1, 4, ALL), // 0xAF
+    op("ORA B", 1, 4, ALL), // 0xB0
+    op("ORA C", 1, 4, ALL), // 0xB1
+    op("ORA D", 1, 4, ALL), // 0xB2
+    op("ORA E", 1, 4, ALL), // 0xB3
+    op("ORA H", 1, 4, ALL), // 0xB4
+    op("ORA L", 1, 4, ALL), // 0xB5
+    op("ORA M", 1, 7, ALL).memory(), // 0xB6
+    op("ORA A", 1, 4, ALL), // 0xB7
+    op("CMP B", 1, 4, ALL), // 0xB8
+    op("CMP C", 1, 4, ALL), // 0xB9
+    op("CMP D", 1, 4, ALL), // 0xBA
+    op("CMP E", 1, 4, ALL), // 0xBB
+    op("CMP H", 1, 4, ALL), // 0xBC
+    op("CMP L", 1, 4, ALL), // 0xBD
+    op("CMP M", 1, 7, ALL).memory(), // 0xBE
+    op("CMP A", 1, 4, ALL), // 0xBF
+    op("RNZ", 1, 5, NONE).taken(11).branch().memory(), // 0xC0
+    op("POP B", 1, 10, NONE).memory(), // 0xC1
+    op("JNZ a16", 3, 10, NONE).branch(), // 0xC2
+    op("JMP a16", 3, 10, NONE).branch(), // 0xC3
+    op("CNZ a16", 3, 11, NONE).taken(17).branch().memory(), // 0xC4
+    op("PUSH B", 1, 11, NONE).memory(), // 0xC5
+    op("ADI d8", 2, 7, ALL), // 0xC6
+    op("RST 0", 1, 11, NONE).branch().memory(), // 0xC7
+    op("RZ", 1, 5, NONE).taken(11).branch().memory(), // 0xC8
+    op("RET", 1, 10, NONE).branch().memory(), // 0xC9
+    op("JZ a16", 3, 10, NONE).branch(), // 0xCA
+    op("JMP a16", 3, 10, NONE).branch().undocumented(), // 0xCB
+    op("CZ a16", 3, 11, NONE).taken(17).branch().memory(), // 0xCC
+    op("CALL a16", 3, 17, NONE).branch().memory(), // 0xCD
+    op("ACI d8", 2, 7, ALL), // 0xCE
+    op("RST 1", 1, 11, NONE).branch().memory(), // 0xCF
+    op("RNC", 1, 5, NONE).taken(11).branch().memory(), // 0xD0
+    op("POP D", 1, 10, NONE).memory(), // 0xD1
+    op("JNC a16", 3, 10, NONE).branch(), // 0xD2
+    op("OUT d8", 2, 10, NONE), // 0xD3
+    op("CNC a16", 3, 11, NONE).taken(17).branch().memory(), // 0xD4
+    op("PUSH D", 1, 11, NONE).memory(), // 0xD5
+    op("SUI d8", 2, 7, ALL), // 0xD6
+    op("RST 2", 1, 11, NONE).branch().memory(), // 0xD7
+    op("RC", 1, 5, NONE).taken(11).branch().memory(), // 0xD8
+    op("RET", 1, 10, NONE).branch().memory().undocumented(), // 0xD9
+    op("JC a16", 3, 10, NONE).branch(), // 0xDA
+    op("IN d8", 2, 10, NONE), // 0xDB
+    op("CC a16", 3, 11, NONE).taken(17).branch().memory(), // 0xDC
+    op("CALL a16", 3, 17, NONE).branch().memory().undocumented(), // 0xDD
+    op("SBI d8", 2, 7, ALL), // 0xDE
+    op("RST 3", 1, 11, NONE).branch().memory(), // 0xDF
+    op("RPO", 1, 5, NONE).taken(11).branch().memory(), // 0xE0
+    op("POP H", 1, 10, NONE).memory(), // 0xE1
+    op("JPO a16", 3, 10, NONE).branch(), // 0xE2
+    op("XTHL", 1, 18, NONE).memory(), // 0xE3
+    op("CPO a16", 3, 11, NONE).taken(17).branch().memory(), // 0xE4
+    op("PUSH H", 1, 11, NONE).memory(), // 0xE5
+    op("ANI d8", 2, 7, ALL), // 0xE6
+    op("RST 4", 1, 11, NONE).branch().memory(), // 0xE7
+    op("RPE", 1, 5, NONE).taken(11).branch().memory(), // 0xE8
+    op("PCHL", 1, 5, NONE).branch(), // 0xE9
+    op("JPE a16", 3, 10, NONE).branch(), // 0xEA
+    op("XCHG", 1, 4, NONE), // 0xEB
+    op("CPE a16", 3, 11, NONE).taken(17).branch().memory(), // 0xEC
+    op("CALL a16", 3, 17, NONE).branch().memory().undocumented(), // 0xED
+    op("XRI d8", 2, 7, ALL), // 0xEE
+    op("RST 5", 1, 11, NONE).branch().memory(), // 0xEF
+    op("RP", 1, 5, NONE).taken(11).branch().memory(), // 0xF0
+    op("POP PSW", 1, 10, ALL).memory(), // 0xF1
+    op("JP a16", 3, 10, NONE).branch(), // 0xF2
+    op("DI", 1, 4, NONE), // 0xF3
+    op("CP a16", 3, 11, NONE).taken(17).branch().memory(), // 0xF4
+    op("PUSH PSW", 1, 11, NONE).memory(), // 0xF5
+    op("ORI d8", 2, 7, ALL), // 0xF6
+    op("RST 6", 1, 11, NONE).branch().memory(), // 0xF7
+    op("RM", 1, 5, NONE).taken(11).branch().memory(), // 0xF8
+    op("SPHL", 1, 5, NONE), // 0xF9
+    op("JM a16", 3, 10, NONE).branch(), // 0xFA
+    op("EI", 1, 4, NONE), // 0xFB
+    op("CM a16", 3, 11, NONE).taken(17).branch().memory(), // 0xFC
+    op("CALL a16", 3, 17, NONE).branch().memory().undocumented(), // 0xFD
+    op("CPI d8", 2, 7, ALL), // 0xFE
+    op("RST 7", 1, 11, NONE).branch().memory() // 0xFF
+];
@@ -0,0 +1,90 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::cpu::bus::Bus;
+use crate::cpu::intel8080::Intel8080;
+
+type Event<B> = Box<dyn FnOnce(&mut Intel8080<B>)>;
+
+// Identifies a scheduled event so that it can be cancelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EventId {
+    at: u64,
+    seq: u64
+}
+
+struct Queue<B: Bus> {
+    now: u64,
+    seq: u64,
+    events: BTreeMap<EventId, Event<B>>
+}
+
+// Events due at given CPU cycle counts, such as a timer underflow or the end
+// of a UART byte. A device keeps a clone (from `Intel8080::scheduler`) and
+// schedules work instead of polling on every step; the CPU runs each event
+// at the first instruction boundary at or after its cycle, giving it the
+// machine so that it can, for example, raise an interrupt. Events due at
+// the same cycle run in the order they were scheduled.
+pub struct Scheduler<B: Bus = Vec<u8>>(Rc<RefCell<Queue<B>>>);
+
+impl<B: Bus> Scheduler<B> {
+    pub fn new() -> Self {
+        Scheduler(Rc::new(RefCell::new(Queue { now: 0, seq: 0, events: BTreeMap::new() })))
+    }
+
+    // The cycle count of the CPU as of its last instruction boundary.
+    pub fn now(&self) -> u64 {
+        self.0.borrow().now
+    }
+
+    // Run `event` once the CPU's cycle count reaches `at`. An event in the
+    // past runs at the next instruction boundary.
+    pub fn schedule_at<F: FnOnce(&mut Intel8080<B>) + 'static>(&self, at: u64, event: F) -> EventId {
+        let mut queue = self.0.borrow_mut();
+        let id = EventId { at, seq: queue.seq };
+        queue.seq += 1;
+        queue.events.insert(id, Box::new(event));
+        id
+    }
+
+    // Run `event` `delay` cycles from now.
+    pub fn schedule_in<F: FnOnce(&mut Intel8080<B>) + 'static>(&self, delay: u64, event: F) -> EventId {
+        let at = self.now() + delay;
+        self.schedule_at(at, event)
+    }
+
+    // Returns whether the event was still pending.
+    pub fn cancel(&self, id: EventId) -> bool {
+        self.0.borrow_mut().events.remove(&id).is_some()
+    }
+
+    // The cycle count at which the earliest pending event is due.
+    pub fn next_due(&self) -> Option<u64> {
+        self.0.borrow().events.keys().next().map(|id| id.at)
+    }
+
+    pub(crate) fn advance(&self, now: u64) {
+        self.0.borrow_mut().now = now;
+    }
+
+    // Remove and return the earliest event due by `now`. The queue is not
+    // borrowed while the event runs, so it can schedule further events.
+    pub(crate) fn take_due(&self, now: u64) -> Option<Event<B>> {
+        let mut queue = self.0.borrow_mut();
+        let id = *queue.events.keys().next().filter(|id| id.at <= now)?;
+        queue.events.remove(&id)
+    }
+}
+
+impl<B: Bus> Clone for Scheduler<B> {
+    fn clone(&self) -> Self {
+        Scheduler(self.0.clone())
+    }
+}
+
+impl<B: Bus> Default for Scheduler<B> {
+    fn default() -> Self {
+        Scheduler::new()
+    }
+}
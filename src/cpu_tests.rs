@@ -0,0 +1,1053 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::cpu::{Reg8, RegPair};
+use crate::cpu::bus::*;
+use crate::cpu::clock::ClockSpeed;
+use crate::cpu::decoder::Instruction;
+use crate::cpu::error::Error;
+use crate::cpu::io::IoDevice;
+use crate::cpu::scheduler::Scheduler;
+use crate::cpu::intel8080::{Budget, Intel8080, PowerOn, RunLimits, Step, StopReason, UndocumentedOpcodes};
+
+#[test]
+fn step_executes_one_instruction() {
+    let mut machine = Intel8080::new();
+    machine.memory = vec![
+        0x3e, 0x42, // MVI A
+        0x01, 0x34, 0x12, // LXI B
+        0x76, // HLT
+    ];
+
+    let step = machine.step().unwrap();
+
+    assert_eq!(step, Step { pc: 0, opcode: 0x3e, instruction: Instruction::Mvi(Reg8::A, 0x42), length: 2, branch_taken: false, cycles: 7, wait_states: 0, held_cycles: 0, interrupt: false });
+    assert_eq!(machine.regs.a, 0x42);
+    assert_eq!(machine.pc, 2);
+    assert_eq!(machine.regs.b, 0);
+
+    let step = machine.step().unwrap();
+
+    assert_eq!(step, Step { pc: 2, opcode: 0x01, instruction: Instruction::Lxi(RegPair::BC, 0x1234), length: 3, branch_taken: false, cycles: 10, wait_states: 0, held_cycles: 0, interrupt: false });
+    assert_eq!(machine.regs.b, 0x12);
+    assert_eq!(machine.regs.c, 0x34);
+}
+
+#[test]
+fn step_reports_branches() {
+    let mut machine = Intel8080::new();
+    machine.flags.zero = 1;
+    machine.memory = vec![
+        0xc2, 0x08, 0x00, // JNZ 0008
+        0xca, 0x08, 0x00, // JZ 0008
+        0, 0,
+        0x76
+    ];
+
+    assert!(!machine.step().unwrap().branch_taken);
+    assert_eq!(machine.pc, 3);
+
+    assert!(machine.step().unwrap().branch_taken);
+    assert_eq!(machine.pc, 8);
+}
+
+#[test]
+fn conditional_call_and_return_cycles() {
+    let mut machine = Intel8080::new();
+    machine.sp = 0x10;
+    machine.flags.zero = 1;
+    machine.memory = vec![0; 0x10];
+    machine.memory[0x00] = 0xc4; // CNZ 0008
+    machine.memory[0x01] = 0x08;
+    machine.memory[0x03] = 0xcc; // CZ 0008
+    machine.memory[0x04] = 0x08;
+    machine.memory[0x08] = 0xc0; // RNZ
+    machine.memory[0x09] = 0xc8; // RZ
+
+    assert_eq!(machine.step().unwrap().cycles, 11);
+    assert_eq!(machine.step().unwrap().cycles, 17);
+    assert_eq!(machine.step().unwrap().cycles, 5);
+    assert_eq!(machine.step().unwrap().cycles, 11);
+    assert_eq!(machine.pc, 0x06);
+    assert_eq!(machine.cycles, 44);
+}
+
+#[test]
+fn run_for_cycles_stops_past_the_budget() {
+    let mut machine = Intel8080::new();
+    machine.memory = vec![0; 0x10];
+    machine.memory[0x00] = 0x3e; // MVI A,1
+    machine.memory[0x01] = 0x01;
+    machine.memory[0x02] = 0xc3; // JMP 0000
+    machine.memory[0x03] = 0x00;
+
+    assert_eq!(machine.run_for_cycles(20).unwrap(), 24);
+    assert_eq!(machine.pc, 0x02);
+    assert_eq!(machine.run_for_cycles(1).unwrap(), 10);
+    assert_eq!(machine.pc, 0x00);
+    assert_eq!(machine.cycles, 34);
+}
+
+// A bus with 256 bytes of ROM at 0x0000 mirrored every 0x100 bytes up to
+// 0x1000, and RAM everywhere above that.
+struct RomBus {
+    rom: Vec<u8>,
+    ram: Vec<u8>
+}
+
+impl Bus for RomBus {
+    fn read(&mut self, addr: u16) -> Result<u8, Error> {
+        if addr < 0x1000 { Ok(self.rom[(addr & 0xff) as usize]) } else { Ok(self.ram[addr as usize]) }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) -> Result<(), Error> {
+        if addr >= 0x1000 { self.ram[addr as usize] = value; }
+        Ok(())
+    }
+}
+
+#[test]
+fn custom_bus_handles_every_access() {
+    let mut rom = vec![0; 0x100];
+    rom[..11].copy_from_slice(&[
+        0x3e, 0x55, // MVI A,55
+        0x32, 0x80, 0x00, // STA 0080
+        0x32, 0x00, 0x20, // STA 2000
+        0xc3, 0x01, 0x01 // JMP 0101 (mirror of 0001)
+    ]);
+    let mut machine = Intel8080::with_bus(RomBus { rom, ram: vec![0; 0x10000] });
+
+    for _ in 0..4 {
+        machine.step().unwrap();
+    }
+
+    assert_eq!(machine.pc, 0x0101);
+    assert_eq!(machine.memory.read(0x0080).unwrap(), 0x00);
+    assert_eq!(machine.memory.read(0x2000).unwrap(), 0x55);
+    assert_eq!(machine.memory.read(0x0301).unwrap(), 0x55);
+}
+
+// Records every OUT and answers IN with the last value written to the port.
+struct Latch {
+    written: Rc<RefCell<Vec<(u8, u8)>>>,
+    ports: [u8; 256]
+}
+
+impl IoDevice for Latch {
+    fn input(&mut self, port: u8) -> u8 {
+        self.ports[port as usize]
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        self.written.borrow_mut().push((port, value));
+        self.ports[port as usize] = value;
+    }
+}
+
+#[test]
+fn in_and_out_reach_the_attached_device() {
+    let written = Rc::new(RefCell::new(Vec::new()));
+    let mut machine = Intel8080::new();
+    machine.attach_io(Latch { written: written.clone(), ports: [0; 256] });
+    machine.memory[..9].copy_from_slice(&[
+        0x3e, 0x5a, // MVI A,5A
+        0xd3, 0x10, // OUT 10
+        0x3e, 0x00, // MVI A,0
+        0xdb, 0x10, // IN 10
+        0x76
+    ]);
+
+    machine.run();
+
+    assert_eq!(machine.pc, 9);
+    assert_eq!(machine.regs.a, 0x5a);
+    assert_eq!(*written.borrow(), vec![(0x10, 0x5a)]);
+}
+
+#[test]
+fn unconnected_ports_read_high() {
+    let mut machine = Intel8080::new();
+    machine.memory[..3].copy_from_slice(&[0xdb, 0x01, 0x76]); // IN 01
+
+    machine.run();
+
+    assert_eq!(machine.regs.a, 0xff);
+    assert_eq!(machine.pc, 3);
+}
+
+#[test]
+fn interrupt_waits_for_ei_and_the_following_instruction() {
+    let mut machine = Intel8080::new();
+    machine.sp = 0x100;
+    machine.memory[..4].copy_from_slice(&[
+        0x00, // NOP
+        0xfb, // EI
+        0x00, // NOP
+        0x00  // NOP
+    ]);
+    machine.memory[0x08] = 0x76;
+
+    machine.interrupt(0xcf); // RST 1
+
+    // INTE is still clear.
+    assert!(!machine.step().unwrap().interrupt);
+    // EI, then the instruction after it, run before the interrupt is taken.
+    assert!(!machine.step().unwrap().interrupt);
+    assert!(!machine.step().unwrap().interrupt);
+
+    let step = machine.step().unwrap();
+    assert!(step.interrupt);
+    assert_eq!(step.pc, 0x03);
+    assert_eq!(step.opcode, 0xcf);
+    assert_eq!(machine.pc, 0x08);
+    assert_eq!(machine.sp, 0xfe);
+    assert_eq!(machine.memory[0xfe], 0x03);
+    assert_eq!(machine.memory[0xff], 0x00);
+    assert_eq!(machine.int_enable, 0);
+    assert!(!machine.interrupt_pending());
+}
+
+#[test]
+fn interrupt_wakes_the_cpu_from_hlt() {
+    let mut machine = Intel8080::new();
+    machine.sp = 0x100;
+    machine.memory[..3].copy_from_slice(&[
+        0xfb, // EI
+        0x76, // HLT
+        0x76  // HLT
+    ]);
+    machine.memory[0x38..0x3a].copy_from_slice(&[
+        0x3c, // INR A
+        0xc9  // RET
+    ]);
+
+    assert!(matches!(machine.run(), StopReason::Halted));
+    assert!(machine.is_halted());
+    assert_eq!(machine.pc, 0x02);
+
+    // Still halted until something interrupts it.
+    assert!(matches!(machine.step(), Err(StopReason::Halted)));
+
+    machine.interrupt(0xff); // RST 7
+    let step = machine.step().unwrap();
+    assert!(step.interrupt);
+    assert!(!machine.is_halted());
+    assert_eq!(machine.memory[0xfe], 0x02);
+
+    assert!(matches!(machine.run(), StopReason::Halted));
+    assert_eq!(machine.regs.a, 1);
+    assert_eq!(machine.pc, 0x03);
+    assert_eq!(machine.sp, 0x100);
+}
+
+#[test]
+fn run_for_cycles_idles_while_halted() {
+    let mut machine = Intel8080::new();
+    machine.memory[0] = 0x76; // HLT
+
+    assert_eq!(machine.run_for_cycles(100).unwrap(), 100);
+    assert!(machine.is_halted());
+    assert_eq!(machine.pc, 0x01);
+    assert_eq!(machine.cycles, 100);
+}
+
+#[test]
+fn run_for_cycles_reports_faults() {
+    let mut machine = Intel8080::new();
+    machine.memory = vec![0x3a, 0x00, 0x10, 0x76]; // LDA 1000H
+
+    match machine.run_for_cycles(1000) {
+        Err(StopReason::Fault(Error::UnmappedAddress(0x1000))) => {}
+        other => panic!("unexpected result: {:?}", other)
+    }
+    assert_eq!(machine.cycles, 0);
+}
+
+#[test]
+fn access_outside_memory_is_a_fault() {
+    let mut machine = Intel8080::new();
+    machine.memory = vec![
+        0x3a, 0x00, 0x10, // LDA 1000
+        0x76
+    ];
+
+    match machine.run() {
+        StopReason::Fault(Error::UnmappedAddress(addr)) => assert_eq!(addr, 0x1000),
+        other => panic!("unexpected stop: {:?}", other)
+    }
+    assert_eq!(machine.pc, 0x00);
+
+    // A CALL whose return address cannot be pushed leaves the PC and SP
+    // where they were.
+    let mut machine = Intel8080::new();
+    machine.memory = vec![0x31, 0x00, 0x10, 0xcd, 0x00, 0x00]; // LXI SP,1000H; CALL 0
+
+    match machine.run() {
+        StopReason::Fault(Error::UnmappedAddress(addr)) => assert_eq!(addr, 0x0fff),
+        other => panic!("unexpected stop: {:?}", other)
+    }
+    assert_eq!(machine.pc, 0x03);
+    assert_eq!(machine.sp, 0x1000);
+
+    // Running off the end of memory faults on the opcode fetch.
+    let mut machine = Intel8080::new();
+    machine.memory = vec![0x00, 0x00];
+
+    match machine.run() {
+        StopReason::Fault(Error::UnmappedAddress(addr)) => assert_eq!(addr, 0x02),
+        other => panic!("unexpected stop: {:?}", other)
+    }
+}
+
+#[test]
+fn undocumented_opcodes_run_as_aliases() {
+    let mut machine = Intel8080::new();
+    machine.sp = 0x100;
+    machine.memory[0x00] = 0x08; // NOP
+    machine.memory[0x01] = 0xcb; // JMP 0010
+    machine.memory[0x02] = 0x10;
+    machine.memory[0x10] = 0xdd; // CALL 0020
+    machine.memory[0x11] = 0x20;
+    machine.memory[0x13] = 0x76;
+    machine.memory[0x20] = 0xd9; // RET
+
+    assert!(matches!(machine.run(), StopReason::Halted));
+    assert_eq!(machine.pc, 0x14);
+    assert_eq!(machine.sp, 0x100);
+    assert_eq!(machine.memory[0xfe], 0x13);
+    assert_eq!(machine.cycles, 4 + 10 + 17 + 10 + 7);
+}
+
+#[test]
+fn undocumented_opcodes_can_trap() {
+    let mut machine = Intel8080::new();
+    machine.set_undocumented_opcodes(UndocumentedOpcodes::Trap);
+    machine.memory[0x00] = 0x3c; // INR A
+    machine.memory[0x01] = 0xed; // Z80 prefix
+    machine.memory[0x02] = 0xb0;
+
+    match machine.run() {
+        StopReason::UndocumentedOpcode { pc, opcode } => {
+            assert_eq!(pc, 0x01);
+            assert_eq!(opcode, 0xed);
+        }
+        other => panic!("unexpected stop: {:?}", other)
+    }
+    assert_eq!(machine.pc, 0x01);
+    assert_eq!(machine.regs.a, 1);
+    assert_eq!(machine.sp, 0);
+}
+
+#[test]
+fn run_for_cycles_reports_traps() {
+    let mut machine = Intel8080::new();
+    machine.set_undocumented_opcodes(UndocumentedOpcodes::Trap);
+    machine.memory[0x00] = 0xcb;
+
+    assert!(matches!(machine.run_for_cycles(1000), Err(StopReason::UndocumentedOpcode { pc: 0x00, opcode: 0xcb })));
+    assert_eq!(machine.cycles, 0);
+}
+
+#[test]
+fn trapped_interrupt_instruction_stays_pending() {
+    let mut machine = Intel8080::new();
+    machine.set_undocumented_opcodes(UndocumentedOpcodes::Trap);
+    machine.memory[..2].copy_from_slice(&[0xfb, 0x76]); // EI; HLT
+    machine.run();
+
+    machine.interrupt_instruction(&[0xdd, 0x00, 0x10]);
+
+    assert!(matches!(machine.step(), Err(StopReason::UndocumentedOpcode { pc: 0x02, opcode: 0xdd })));
+    assert!(machine.interrupt_pending());
+    assert_eq!(machine.int_enable, 1);
+    assert!(machine.is_halted());
+    assert_eq!(machine.pc, 0x02);
+}
+
+#[test]
+fn stack_wraps_instead_of_panicking() {
+    let mut machine = Intel8080::new();
+    machine.regs.b = 0x12;
+    machine.regs.c = 0x34;
+    machine.memory[..2].copy_from_slice(&[
+        0xc5, // PUSH B
+        0x76
+    ]);
+
+    machine.run();
+
+    assert_eq!(machine.sp, 0xfffe);
+    assert_eq!(machine.memory[0xffff], 0x12);
+    assert_eq!(machine.memory[0xfffe], 0x34);
+}
+
+#[test]
+fn addresses_wrap_at_the_top_of_memory() {
+    let mut machine = Intel8080::new();
+    machine.pc = 0xfffe;
+    machine.sp = 0xffff;
+    machine.memory[0xfffe] = 0x31; // LXI SP,0000 with its operand wrapping
+    machine.memory[0xffff] = 0x00;
+    machine.memory[0x0000] = 0x00;
+    machine.memory[0x0001] = 0x3b; // DCX SP
+    machine.memory[0x0002] = 0x33; // INX SP
+    machine.memory[0x0003] = 0xcd; // CALL FFFF
+    machine.memory[0x0004] = 0xff;
+    machine.memory[0x0005] = 0xff;
+
+    let step = machine.step().unwrap();
+    assert_eq!(step.pc, 0xfffe);
+    assert_eq!(machine.pc, 0x0001);
+    assert_eq!(machine.sp, 0x0000);
+
+    machine.step().unwrap();
+    assert_eq!(machine.sp, 0xffff);
+
+    machine.step().unwrap();
+    assert_eq!(machine.sp, 0x0000);
+
+    machine.step().unwrap();
+    assert_eq!(machine.pc, 0xffff);
+    assert_eq!(machine.sp, 0xfffe);
+    assert_eq!(machine.memory[0xfffe], 0x06);
+
+    // RET pops the return address from either side of the wrap.
+    machine.pc = 0x0010;
+    machine.memory[0x0010] = 0xc9;
+    machine.sp = 0xffff;
+    machine.memory[0xffff] = 0x06;
+    machine.memory[0x0000] = 0x34;
+    machine.step().unwrap();
+    assert_eq!(machine.pc, 0x3406);
+    assert_eq!(machine.sp, 0x0001);
+}
+
+#[test]
+fn typed_register_access() {
+    let mut machine = Intel8080::new();
+
+    assert!(machine.regs.set(Reg8::H, 0x12));
+    assert!(machine.regs.set(Reg8::L, 0x34));
+    assert_eq!(machine.regs.pair(RegPair::HL), Some(0x1234));
+    assert_eq!(machine.regs.get(Reg8::L), Some(0x34));
+
+    assert!(machine.regs.set_pair(RegPair::BC, 0xbeef));
+    assert_eq!((machine.regs.b, machine.regs.c), (0xbe, 0xef));
+
+    // M, SP and PSW live outside Register.
+    assert_eq!(machine.regs.get(Reg8::M), None);
+    assert!(!machine.regs.set(Reg8::M, 0));
+    assert_eq!(machine.regs.pair(RegPair::SP), None);
+    assert!(!machine.regs.set_pair(RegPair::PSW, 0));
+    assert_eq!(machine.regs.a, 0);
+
+    machine.set(Reg8::M, 0x55);
+    assert_eq!(machine.memory[0x1234], 0x55);
+    assert_eq!(machine.get(Reg8::M), 0x55);
+
+    machine.set_pair(RegPair::SP, 0x2400);
+    assert_eq!(machine.sp, 0x2400);
+
+    machine.set_pair(RegPair::PSW, 0x42ff);
+    assert_eq!(machine.regs.a, 0x42);
+    assert_eq!(machine.flags.carry, 1);
+    assert_eq!(machine.pair(RegPair::PSW), 0x42d7);
+}
+
+#[test]
+fn pop_fills_the_low_register_first() {
+    let mut machine = Intel8080::new();
+    machine.sp = 0x10;
+    machine.memory[0x10] = 0x34;
+    machine.memory[0x11] = 0x12;
+    machine.memory[0x00] = 0xe1; // POP H
+
+    machine.step().unwrap();
+
+    assert_eq!(machine.pair(RegPair::HL), 0x1234);
+    assert_eq!(machine.sp, 0x12);
+}
+
+#[test]
+fn dad_sets_carry_for_every_pair() {
+    let mut machine = Intel8080::new();
+    machine.memory[..2].copy_from_slice(&[
+        0x29, // DAD H
+        0x39 // DAD SP
+    ]);
+    machine.regs.h = 0x80;
+    machine.regs.l = 0x01;
+
+    machine.step().unwrap();
+    assert_eq!((machine.regs.h, machine.regs.l), (0x00, 0x02));
+    assert_eq!(machine.flags.carry, 1);
+
+    machine.sp = 0xfffe;
+    machine.step().unwrap();
+    assert_eq!((machine.regs.h, machine.regs.l), (0x00, 0x00));
+    assert_eq!(machine.flags.carry, 1);
+}
+
+#[test]
+fn load_program_reports_errors() {
+    let mut machine = Intel8080::new();
+
+    match machine.load_program("/nonexistent/program.bin") {
+        Err(Error::Io(_)) => {}
+        other => panic!("unexpected result: {:?}", other)
+    }
+
+    let path = std::env::temp_dir().join("intel8080_too_large.bin");
+    std::fs::write(&path, vec![0; 0x10001]).unwrap();
+
+    match machine.load_program(path.to_str().unwrap()) {
+        Err(Error::ProgramTooLarge(size)) => assert_eq!(size, 0x10001),
+        other => panic!("unexpected result: {:?}", other)
+    }
+    std::fs::remove_file(&path).unwrap();
+
+    let error = machine.load_image(0xfff0, &[0; 0x20]).unwrap_err();
+    assert!(matches!(error, Error::ImageOutOfRange { origin: 0xfff0, size: 0x20 }));
+    assert_eq!(error.to_string(), "32 bytes loaded at FFF0H run past the end of memory");
+}
+
+// Collects the bus cycles a CPU reports, for inspection after it has run.
+#[derive(Clone, Default)]
+struct Recorder(Rc<RefCell<Vec<BusCycle>>>);
+
+impl BusObserver for Recorder {
+    fn cycle(&mut self, cycle: &BusCycle) {
+        self.0.borrow_mut().push(*cycle);
+    }
+}
+
+fn cycle(m: u8, status: u8, addr: u16, data: u8) -> BusCycle {
+    BusCycle { m, status, addr, data }
+}
+
+#[test]
+fn bus_cycles_of_call_and_ret() {
+    let recorder = Recorder::default();
+    let mut machine = Intel8080::new();
+    machine.attach_bus_observer(recorder.clone());
+    machine.sp = 0x100;
+    machine.memory[0x00] = 0xcd; // CALL 0010H
+    machine.memory[0x01] = 0x10;
+    machine.memory[0x02] = 0x00;
+    machine.memory[0x10] = 0xc9; // RET
+
+    machine.step().unwrap();
+    machine.step().unwrap();
+
+    assert_eq!(*recorder.0.borrow(), vec![
+        cycle(1, INSTRUCTION_FETCH, 0x0000, 0xcd),
+        cycle(2, MEMORY_READ, 0x0001, 0x10),
+        cycle(3, MEMORY_READ, 0x0002, 0x00),
+        cycle(4, STACK_WRITE, 0x00ff, 0x00),
+        cycle(5, STACK_WRITE, 0x00fe, 0x03),
+        cycle(1, INSTRUCTION_FETCH, 0x0010, 0xc9),
+        cycle(2, STACK_READ, 0x00fe, 0x03),
+        cycle(3, STACK_READ, 0x00ff, 0x00)
+    ]);
+}
+
+#[test]
+fn bus_cycles_of_io_halt_and_interrupt() {
+    let recorder = Recorder::default();
+    let mut machine = Intel8080::new();
+    machine.attach_bus_observer(recorder.clone());
+    let mut ports = [0; 256];
+    ports[7] = 0x42;
+    machine.attach_io(Latch { written: Rc::new(RefCell::new(Vec::new())), ports });
+    machine.sp = 0x100;
+    machine.memory = vec![
+        0xfb, // EI
+        0xdb, 0x07, // IN 07H
+        0xd3, 0x09, // OUT 09H
+        0x76 // HLT
+    ];
+    machine.memory.resize(0x10000, 0);
+
+    for _ in 0..4 {
+        machine.step().unwrap();
+    }
+    machine.interrupt(0xcf); // RST 1
+    machine.step().unwrap();
+
+    assert_eq!(recorder.0.borrow()[1..], [
+        cycle(1, INSTRUCTION_FETCH, 0x0001, 0xdb),
+        cycle(2, MEMORY_READ, 0x0002, 0x07),
+        cycle(3, INPUT_READ, 0x0707, 0x42),
+        cycle(1, INSTRUCTION_FETCH, 0x0003, 0xd3),
+        cycle(2, MEMORY_READ, 0x0004, 0x09),
+        cycle(3, OUTPUT_WRITE, 0x0909, 0x42),
+        cycle(1, INSTRUCTION_FETCH, 0x0005, 0x76),
+        cycle(2, HALT_ACKNOWLEDGE, 0x0006, 0xff),
+        cycle(1, INTERRUPT_ACKNOWLEDGE_WHILE_HALT, 0x0006, 0xcf),
+        cycle(2, STACK_WRITE, 0x00ff, 0x00),
+        cycle(3, STACK_WRITE, 0x00fe, 0x06)
+    ]);
+}
+
+#[test]
+fn xthl_writes_h_before_l() {
+    let recorder = Recorder::default();
+    let mut machine = Intel8080::new();
+    machine.attach_bus_observer(recorder.clone());
+    machine.sp = 0x100;
+    machine.set_pair(RegPair::HL, 0x1234);
+    machine.memory[0x00] = 0xe3; // XTHL
+
+    machine.step().unwrap();
+
+    let statuses: Vec<(u8, u16)> = recorder.0.borrow().iter().map(|c| (c.status, c.addr)).collect();
+    assert_eq!(statuses, vec![
+        (INSTRUCTION_FETCH, 0x0000),
+        (STACK_READ, 0x0100),
+        (STACK_READ, 0x0101),
+        (STACK_WRITE, 0x0101),
+        (STACK_WRITE, 0x0100)
+    ]);
+}
+
+#[test]
+fn wait_states_stretch_slow_regions() {
+    let mut machine = Intel8080::new();
+    machine.set_memory_wait_states(0x0000..=0x0fff, 1); // slow ROM
+    machine.set_memory_wait_states(0x0800..=0x0fff, 0); // fast scratch RAM
+    machine.set_port_wait_states(0x10, 2);
+    machine.memory[..8].copy_from_slice(&[
+        0x3a, 0x00, 0x08, // LDA 0800H
+        0xd3, 0x10, // OUT 10H
+        0xd3, 0x11, // OUT 11H
+        0x76 // HLT
+    ]);
+
+    let lda = machine.step().unwrap();
+    let slow_out = machine.step().unwrap();
+    let fast_out = machine.step().unwrap();
+
+    // Three fetches from ROM, then the operand read from RAM.
+    assert_eq!((lda.cycles, lda.wait_states), (13, 3));
+    // Two fetches from ROM and the port cycle.
+    assert_eq!((slow_out.cycles, slow_out.wait_states), (10, 4));
+    assert_eq!(fast_out.wait_states, 2);
+    assert_eq!(machine.wait_states, 9);
+    assert_eq!(machine.cycles, 13 + 10 + 10 + 9);
+}
+
+#[test]
+fn memory_wait_states_can_be_changed() {
+    let mut machine = Intel8080::new();
+    machine.memory[..2].copy_from_slice(&[0x00, 0x00]); // NOP; NOP
+
+    // Setting a range again replaces it rather than piling up.
+    for wait_states in 1..=3 {
+        machine.set_memory_wait_states(0x0000..=0x00ff, wait_states);
+    }
+    assert_eq!(machine.step().unwrap().wait_states, 3);
+
+    machine.clear_memory_wait_states();
+    assert_eq!(machine.step().unwrap().wait_states, 0);
+}
+
+#[test]
+fn ready_line_stalls_the_next_machine_cycle() {
+    let mut machine = Intel8080::new();
+    let ready = machine.ready_line();
+    machine.memory[..2].copy_from_slice(&[0x00, 0x76]); // NOP; HLT
+
+    ready.hold_low(5);
+    assert!(ready.is_low());
+    let step = machine.step().unwrap();
+
+    assert!(!ready.is_low());
+    assert_eq!(step.wait_states, 5);
+    assert_eq!(machine.cycles, 4 + 5);
+
+    // Interrupt acknowledge cycles can be stretched too.
+    machine.step().unwrap();
+    ready.hold_low(3);
+    machine.interrupt(0xc7);
+    machine.int_enable = 1;
+    assert_eq!(machine.step().unwrap().wait_states, 3);
+}
+
+// A DMA controller that copies `block` into memory at `addr`, one byte per
+// HOLD, once it has been started.
+struct Dma {
+    block: Rc<RefCell<Vec<u8>>>,
+    addr: u16
+}
+
+impl BusMaster<Vec<u8>> for Dma {
+    fn hold(&self) -> bool {
+        !self.block.borrow().is_empty()
+    }
+
+    fn transfer(&mut self, memory: &mut Vec<u8>) -> u64 {
+        let byte = self.block.borrow_mut().remove(0);
+        memory.write(self.addr, byte).unwrap();
+        self.addr += 1;
+        4
+    }
+}
+
+#[test]
+fn bus_master_takes_the_bus_between_machine_cycles() {
+    let block = Rc::new(RefCell::new(Vec::new()));
+    let mut machine = Intel8080::new();
+    machine.attach_bus_master(Dma { block: block.clone(), addr: 0x200 });
+    machine.memory[..4].copy_from_slice(&[
+        0x00, // NOP
+        0x3a, 0x01, 0x02, // LDA 0201H
+    ]);
+
+    let step = machine.step().unwrap();
+    assert_eq!(step.held_cycles, 0);
+
+    // HOLD is granted after the fetch of LDA, so the operand read already
+    // sees the transferred bytes.
+    block.borrow_mut().extend_from_slice(&[0x11, 0x22, 0x33]);
+    let step = machine.step().unwrap();
+
+    assert_eq!(step.held_cycles, 12);
+    assert_eq!(machine.regs.a, 0x22);
+    assert_eq!(&machine.memory[0x200..0x203], &[0x11, 0x22, 0x33]);
+    assert_eq!(machine.held_cycles, 12);
+    assert_eq!(machine.cycles, 4 + 13 + 12);
+}
+
+#[test]
+fn halted_cpu_grants_the_bus() {
+    let block = Rc::new(RefCell::new(Vec::new()));
+    let mut machine = Intel8080::new();
+    machine.attach_bus_master(Dma { block: block.clone(), addr: 0x200 });
+    machine.memory[0] = 0x76; // HLT
+    machine.step().unwrap();
+
+    block.borrow_mut().push(0x99);
+
+    assert!(matches!(machine.step(), Err(StopReason::Halted)));
+    assert_eq!(machine.memory[0x200], 0x99);
+    assert_eq!(machine.held_cycles, 4);
+}
+
+// A bus master that asks for the bus forever and gives nothing back.
+struct Hog;
+
+impl BusMaster<Vec<u8>> for Hog {
+    fn hold(&self) -> bool {
+        true
+    }
+
+    fn transfer(&mut self, _memory: &mut Vec<u8>) -> u64 {
+        0
+    }
+}
+
+#[test]
+fn bus_master_that_never_lets_go_does_not_hang_the_cpu() {
+    let mut machine = Intel8080::new();
+    machine.attach_bus_master(Hog);
+
+    assert_eq!(machine.run_for_cycles(100).unwrap(), 100);
+    assert_eq!(machine.held_cycles, 0);
+}
+
+#[test]
+fn stopped_instructions_keep_their_wait_states() {
+    let mut machine = Intel8080::new();
+    machine.memory = vec![0x3a, 0x00, 0x80, 0x76]; // LDA 8000H
+    machine.set_memory_wait_states(0x0000..=0xffff, 1);
+
+    assert!(matches!(machine.step(), Err(StopReason::Fault(Error::UnmappedAddress(0x8000)))));
+    assert_eq!(machine.wait_states, 4);
+    assert_eq!(machine.cycles, 4);
+
+    let mut machine = Intel8080::new();
+    machine.memory[0] = 0xcb;
+    machine.set_undocumented_opcodes(UndocumentedOpcodes::Trap);
+    machine.set_memory_wait_states(0x0000..=0xffff, 1);
+    let block = Rc::new(RefCell::new(vec![0x55]));
+    machine.attach_bus_master(Dma { block, addr: 0x200 });
+
+    assert!(matches!(machine.step(), Err(StopReason::UndocumentedOpcode { pc: 0, opcode: 0xcb })));
+    assert_eq!(machine.wait_states, 3);
+    assert_eq!(machine.held_cycles, 4);
+    assert_eq!(machine.cycles, 3 + 4);
+}
+
+#[test]
+fn reset_keeps_registers_and_memory() {
+    let mut machine = Intel8080::new();
+    machine.memory[..3].copy_from_slice(&[0x3e, 0x42, 0x76]); // MVI A,42H; HLT
+    machine.sp = 0x1234;
+    machine.int_enable = 1;
+    machine.run();
+
+    machine.reset();
+
+    assert_eq!(machine.pc, 0);
+    assert_eq!(machine.int_enable, 0);
+    assert!(!machine.is_halted());
+    assert_eq!(machine.regs.a, 0x42);
+    assert_eq!(machine.sp, 0x1234);
+    assert_eq!(machine.memory[1], 0x42);
+}
+
+#[test]
+fn power_on_policies() {
+    let mut machine = Intel8080::new();
+    machine.pc = 0x100;
+
+    machine.power_on(PowerOn::Pattern(0xe5));
+
+    assert_eq!(machine.pc, 0);
+    assert_eq!(machine.regs.b, 0xe5);
+    assert_eq!(machine.regs.a, 0xe5);
+    assert_eq!(machine.sp, 0xe5e5);
+    assert!(machine.memory.iter().all(|&byte| byte == 0xe5));
+
+    machine.power_on(PowerOn::Zeroed);
+
+    assert_eq!(machine.pair(RegPair::HL), 0);
+    assert_eq!(machine.pair(RegPair::PSW), 0x0002);
+    assert!(machine.memory.iter().all(|&byte| byte == 0));
+
+    let mut other = Intel8080::new();
+    machine.power_on(PowerOn::Random(8080));
+    other.power_on(PowerOn::Random(8080));
+
+    assert_eq!(machine.memory, other.memory);
+    assert_eq!(machine.pair(RegPair::PSW), other.pair(RegPair::PSW));
+    assert!(machine.memory.iter().any(|&byte| byte != machine.memory[0]));
+
+    other.power_on(PowerOn::Random(8085));
+    assert_ne!(machine.memory, other.memory);
+
+    // Neighbouring seeds give different states.
+    machine.power_on(PowerOn::Random(2));
+    other.power_on(PowerOn::Random(3));
+    assert_ne!(machine.memory, other.memory);
+}
+
+#[test]
+fn power_on_leaves_rom_alone() {
+    let mut machine = Intel8080::with_bus(RomBus { rom: vec![0x76; 0x100], ram: vec![0; 0x10000] });
+
+    machine.power_on(PowerOn::Pattern(0xaa));
+
+    assert_eq!(machine.memory.rom, vec![0x76; 0x100]);
+    assert!(machine.memory.ram[0x1000..].iter().all(|&byte| byte == 0xaa));
+    assert!(matches!(machine.run(), StopReason::Halted));
+}
+
+#[test]
+fn interrupt_can_supply_a_call() {
+    let recorder = Recorder::default();
+    let mut machine = Intel8080::new();
+    machine.attach_bus_observer(recorder.clone());
+    machine.sp = 0x100;
+    machine.int_enable = 1;
+    machine.pc = 0x40;
+    machine.memory[0x40] = 0x00; // NOP
+    machine.memory[0x1234] = 0xc9; // RET
+
+    machine.interrupt_instruction(&[0xcd, 0x34, 0x12]); // CALL 1234H
+    let step = machine.step().unwrap();
+
+    assert_eq!(step.instruction, Instruction::Call(0x1234));
+    assert!(step.interrupt);
+    assert_eq!(step.cycles, 17);
+    assert_eq!(machine.pc, 0x1234);
+    assert_eq!(machine.sp, 0xfe);
+    // The interrupted instruction, not the one after it, is returned to.
+    assert_eq!((machine.memory[0xfe], machine.memory[0xff]), (0x40, 0x00));
+    assert_eq!(recorder.0.borrow()[..3], [
+        cycle(1, INTERRUPT_ACKNOWLEDGE, 0x0040, 0xcd),
+        cycle(2, INTERRUPT_OPERAND_READ, 0x0040, 0x34),
+        cycle(3, INTERRUPT_OPERAND_READ, 0x0040, 0x12)
+    ]);
+
+    machine.step().unwrap();
+    assert_eq!(machine.pc, 0x40);
+}
+
+#[test]
+fn short_interrupt_instruction_reads_a_floating_bus() {
+    let mut machine = Intel8080::new();
+    machine.sp = 0x100;
+    machine.int_enable = 1;
+
+    machine.interrupt_instruction(&[0xc3]); // JMP with nothing after it
+
+    assert_eq!(machine.step().unwrap().instruction, Instruction::Jmp(0xffff));
+}
+
+#[test]
+fn run_limits_stop_endless_programs() {
+    let mut machine = Intel8080::new();
+    machine.memory[..3].copy_from_slice(&[0xc3, 0x00, 0x00]); // JMP 0000H
+
+    assert!(matches!(machine.run_with_limits(RunLimits::new().instructions(100)), StopReason::BudgetExhausted(Budget::Instructions)));
+    assert_eq!(machine.cycles, 1000);
+
+    assert!(matches!(machine.run_with_limits(RunLimits::new().cycles(95)), StopReason::BudgetExhausted(Budget::Cycles)));
+    assert_eq!(machine.cycles, 1100);
+
+    let limits = RunLimits::new().instructions(u64::MAX).timeout(Duration::from_millis(10));
+    assert!(matches!(machine.run_with_limits(limits), StopReason::BudgetExhausted(Budget::Time)));
+
+    let limits = RunLimits::new().deadline(Instant::now());
+    assert!(matches!(machine.run_with_limits(limits), StopReason::BudgetExhausted(Budget::Time)));
+}
+
+#[test]
+fn breakpoints_stop_before_the_instruction() {
+    let mut machine = Intel8080::new();
+    machine.memory[..6].copy_from_slice(&[
+        0x3c, // INR A
+        0x3c, // INR A
+        0x3c, // INR A
+        0xc3, 0x01, 0x00 // JMP 0001H
+    ]);
+    machine.add_breakpoint(0x0002);
+
+    assert!(matches!(machine.run(), StopReason::Breakpoint { pc: 0x0002 }));
+    assert_eq!(machine.regs.a, 2);
+
+    // Resuming runs the instruction at the breakpoint before checking again.
+    assert!(matches!(machine.run(), StopReason::Breakpoint { pc: 0x0002 }));
+    assert_eq!(machine.regs.a, 4);
+
+    machine.remove_breakpoint(0x0002);
+    assert!(matches!(machine.run_with_limits(RunLimits::new().instructions(4)), StopReason::BudgetExhausted(_)));
+    assert_eq!(machine.regs.a, 7);
+}
+
+#[test]
+fn breakpoints_fire_where_a_run_starts() {
+    let mut machine = Intel8080::new();
+    machine.memory[..4].copy_from_slice(&[
+        0x3c, // INR A
+        0xc3, 0x00, 0x00 // JMP 0000H
+    ]);
+    machine.add_breakpoint(0x0000);
+
+    // A breakpoint at the entry point stops the run before anything runs.
+    assert!(matches!(machine.run(), StopReason::Breakpoint { pc: 0x0000 }));
+    assert_eq!(machine.regs.a, 0);
+
+    // Stopping somewhere else does not make the next run skip it.
+    machine.remove_breakpoint(0x0000);
+    machine.pc = 0x0001;
+    assert!(matches!(machine.run_with_limits(RunLimits::new().instructions(1)), StopReason::BudgetExhausted(Budget::Instructions)));
+    assert_eq!(machine.pc, 0x0000);
+    machine.add_breakpoint(0x0000);
+    assert!(matches!(machine.run(), StopReason::Breakpoint { pc: 0x0000 }));
+    assert_eq!(machine.regs.a, 0);
+
+    // Only resuming from the breakpoint steps over it.
+    assert!(matches!(machine.run(), StopReason::Breakpoint { pc: 0x0000 }));
+    assert_eq!(machine.regs.a, 1);
+}
+
+// Re-arms itself every `period` cycles and raises RST 7 each time.
+fn periodic_interrupt(scheduler: Scheduler, period: u64) {
+    let next = scheduler.clone();
+    scheduler.schedule_in(period, move |machine: &mut Intel8080| {
+        machine.interrupt(0xff);
+        periodic_interrupt(next, period);
+    });
+}
+
+#[test]
+fn scheduled_events_wake_a_halted_cpu() {
+    let mut machine = Intel8080::new();
+    machine.sp = 0x100;
+    machine.memory[..3].copy_from_slice(&[
+        0xfb, // EI
+        0x76, // HLT
+        0x76 // HLT
+    ]);
+    machine.memory[0x38..0x3a].copy_from_slice(&[0x3c, 0xc9]); // INR A; RET
+    periodic_interrupt(machine.scheduler(), 1000);
+
+    // The CPU halts at cycle 11 and idles until the first tick.
+    assert!(matches!(machine.run_with_limits(RunLimits::new().cycles(1500)), StopReason::BudgetExhausted(Budget::Cycles)));
+    assert_eq!(machine.regs.a, 1);
+    assert_eq!(machine.cycles, 1500);
+    assert!(machine.is_halted());
+
+    // Interrupts are off again, so the next tick leaves the CPU halted.
+    assert_eq!(machine.run_for_cycles(1000).unwrap(), 1000);
+    assert_eq!(machine.regs.a, 1);
+    assert!(machine.interrupt_pending());
+}
+
+#[test]
+fn scheduled_events_run_in_order_and_can_be_cancelled() {
+    let order = Rc::new(RefCell::new(Vec::new()));
+    let mut machine = Intel8080::new();
+    let scheduler = machine.scheduler();
+    let record = |name: &'static str| {
+        let order = order.clone();
+        move |machine: &mut Intel8080| order.borrow_mut().push((name, machine.cycles))
+    };
+
+    scheduler.schedule_at(8, record("second"));
+    scheduler.schedule_at(3, record("first"));
+    scheduler.schedule_at(8, record("third"));
+    let cancelled = scheduler.schedule_at(5, record("cancelled"));
+    assert_eq!(scheduler.next_due(), Some(3));
+
+    assert!(scheduler.cancel(cancelled));
+    assert!(!scheduler.cancel(cancelled));
+    machine.run_for_cycles(12).unwrap(); // NOPs
+
+    // Events run at the first instruction boundary at or after their cycle.
+    assert_eq!(*order.borrow(), vec![("first", 4), ("second", 8), ("third", 8)]);
+    assert_eq!(scheduler.now(), 8);
+    assert_eq!(scheduler.next_due(), None);
+}
+
+#[test]
+fn clock_speed_paces_runs() {
+    let mut machine = Intel8080::new();
+    machine.memory[..3].copy_from_slice(&[0xc3, 0x00, 0x00]); // JMP 0000H
+
+    machine.run_with_limits(RunLimits::new().cycles(20_000));
+    let report = machine.speed_report();
+    assert_eq!(report.target, ClockSpeed::Turbo);
+    assert_eq!(report.cycles, 20_000);
+
+    // 20,000 cycles take 20 ms at 1 MHz.
+    machine.set_clock_speed(ClockSpeed::mhz(1.0));
+    machine.run_with_limits(RunLimits::new().cycles(20_000));
+    let report = machine.speed_report();
+
+    assert_eq!(report.target, ClockSpeed::Hz(1_000_000));
+    assert_eq!(report.cycles, 20_000);
+    assert!(report.elapsed >= Duration::from_millis(19), "{:?}", report);
+    assert!(report.achieved_hz() < 1_100_000.0, "{:?}", report);
+
+    assert_eq!(ClockSpeed::default(), ClockSpeed::Hz(2_000_000));
+    assert_eq!(ClockSpeed::INTEL_8080A_1, ClockSpeed::mhz(3.125));
+}
+
+#[test]
+fn throttled_runs_keep_their_deadline() {
+    let mut machine = Intel8080::new();
+    machine.memory[0] = 0x76; // HLT
+    machine.set_clock_speed(ClockSpeed::mhz(1.0));
+    // Two seconds away at 1 MHz.
+    machine.scheduler().schedule_at(2_000_000, |machine: &mut Intel8080| machine.interrupt(0xff));
+
+    let started = Instant::now();
+    let reason = machine.run_with_limits(RunLimits::new().timeout(Duration::from_millis(50)));
+
+    assert!(matches!(reason, StopReason::BudgetExhausted(Budget::Time)), "{:?}", reason);
+    assert!(started.elapsed() < Duration::from_millis(500), "{:?}", started.elapsed());
+}
@@ -0,0 +1,99 @@
+use crate::cpu::decoder::{decode, instruction_length, is_undocumented, Condition, Instruction};
+use crate::cpu::{Reg8, RegPair};
+use crate::cpu::opcodes::{OPCODES, FLAG_CARRY};
+
+#[test]
+fn decode_operands() {
+    assert_eq!(decode(&[0x3e, 0x03]), (Instruction::Mvi(Reg8::A, 0x03), 2));
+    assert_eq!(decode(&[0x31, 0x00, 0x24]), (Instruction::Lxi(RegPair::SP, 0x2400), 3));
+    assert_eq!(decode(&[0xc2, 0x00, 0x01]), (Instruction::Jcc(Condition::NotZero, 0x0100), 3));
+    assert_eq!(decode(&[0xfc, 0x34, 0x12]), (Instruction::Ccc(Condition::Minus, 0x1234), 3));
+    assert_eq!(decode(&[0xe8]), (Instruction::Rcc(Condition::ParityEven), 1));
+    assert_eq!(decode(&[0x7e]), (Instruction::Mov(Reg8::A, Reg8::M), 1));
+    assert_eq!(decode(&[0x70]), (Instruction::Mov(Reg8::M, Reg8::B), 1));
+    assert_eq!(decode(&[0x76]), (Instruction::Hlt, 1));
+    assert_eq!(decode(&[0x9e]), (Instruction::Sbb(Reg8::M), 1));
+    assert_eq!(decode(&[0xf5]), (Instruction::Push(RegPair::PSW), 1));
+    assert_eq!(decode(&[0x39]), (Instruction::Dad(RegPair::SP), 1));
+    assert_eq!(decode(&[0xef]), (Instruction::Rst(5), 1));
+    assert_eq!(decode(&[0xdb, 0x10]), (Instruction::In(0x10), 2));
+    assert_eq!(decode(&[0xfe, 0x7f]), (Instruction::Cpi(0x7f), 2));
+}
+
+#[test]
+fn decode_with_missing_operand_bytes() {
+    assert_eq!(decode(&[0xcd]), (Instruction::Call(0x0000), 3));
+    assert_eq!(decode(&[0xcd, 0x12]), (Instruction::Call(0x0012), 3));
+}
+
+#[test]
+fn decode_undocumented_aliases() {
+    for &opcode in &[0x08, 0x10, 0x18, 0x20, 0x28, 0x30, 0x38] {
+        assert_eq!(decode(&[opcode]), (Instruction::Nop, 1));
+    }
+    assert_eq!(decode(&[0xcb, 0x34, 0x12]), (Instruction::Jmp(0x1234), 3));
+    assert_eq!(decode(&[0xd9]), (Instruction::Ret, 1));
+    for &opcode in &[0xdd, 0xed, 0xfd] {
+        assert_eq!(decode(&[opcode, 0x34, 0x12]), (Instruction::Call(0x1234), 3));
+    }
+
+    assert_eq!((0..=255_u8).filter(|&opcode| is_undocumented(opcode)).count(), 12);
+    assert!(!is_undocumented(0x00));
+    assert!(!is_undocumented(0xc3));
+}
+
+#[test]
+fn instruction_lengths() {
+    let three_bytes = [
+        0x01, 0x11, 0x21, 0x31, 0x22, 0x2a, 0x32, 0x3a,
+        0xc2, 0xc3, 0xc4, 0xca, 0xcc, 0xcd, 0xd2, 0xd4, 0xda, 0xdc,
+        0xe2, 0xe4, 0xea, 0xec, 0xf2, 0xf4, 0xfa, 0xfc,
+        0xcb, 0xdd, 0xed, 0xfd
+    ];
+    let two_bytes = [
+        0x06, 0x0e, 0x16, 0x1e, 0x26, 0x2e, 0x36, 0x3e,
+        0xc6, 0xce, 0xd3, 0xd6, 0xdb, 0xde, 0xe6, 0xee, 0xf6, 0xfe
+    ];
+
+    for opcode in 0..=255_u8 {
+        let expected = if three_bytes.contains(&opcode) {
+            3
+        } else if two_bytes.contains(&opcode) {
+            2
+        } else {
+            1
+        };
+        assert_eq!(instruction_length(opcode), expected, "opcode {:02x}", opcode);
+    }
+}
+
+#[test]
+fn opcode_table_agrees_with_decoder() {
+    for opcode in 0..=255_u8 {
+        let info = &OPCODES[opcode as usize];
+        let (instruction, length) = decode(&[opcode]);
+
+        assert_eq!(info.length as usize, length, "opcode {:02x}", opcode);
+        assert_eq!(info.undocumented, is_undocumented(opcode), "opcode {:02x}", opcode);
+
+        let mnemonic = info.mnemonic
+            .replace("d16", "0000H")
+            .replace("a16", "0000H")
+            .replace("d8", "00H");
+        assert_eq!(mnemonic, instruction.to_string(), "opcode {:02x}", opcode);
+
+        let branch = matches!(instruction,
+            Instruction::Jmp(_) | Instruction::Jcc(..) | Instruction::Call(_) | Instruction::Ccc(..) |
+            Instruction::Ret | Instruction::Rcc(_) | Instruction::Rst(_) | Instruction::Pchl);
+        assert_eq!(info.branch, branch, "opcode {:02x}", opcode);
+
+        let conditional = matches!(instruction, Instruction::Ccc(..) | Instruction::Rcc(_));
+        assert_eq!(info.cycles_taken != info.cycles, conditional, "opcode {:02x}", opcode);
+    }
+
+    assert_eq!(OPCODES[0x3f].flags, FLAG_CARRY);
+    assert_eq!(OPCODES[0x7e].mnemonic, "MOV A,M");
+    assert!(OPCODES[0x7e].memory);
+    assert!(!OPCODES[0x78].memory);
+    assert_eq!((OPCODES[0xc4].cycles, OPCODES[0xc4].cycles_taken), (11, 17));
+}
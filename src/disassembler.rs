@@ -0,0 +1,190 @@
+use std::fmt;
+
+use crate::cpu::bus::Bus;
+use crate::cpu::decoder::{decode, is_undocumented, Condition, Instruction};
+use crate::cpu::error::Error;
+use crate::cpu::{Reg8, RegPair};
+
+// One line of a listing: where it lives, the bytes it was decoded from and
+// what they mean. `instruction` is None for bytes that do not make up a
+// whole instruction, which are listed as DB. Undocumented opcodes are
+// listed as DB too, with the instruction they alias in a comment, so that
+// the listing assembles back to the same bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub instruction: Option<Instruction>
+}
+
+// Decode `bytes`, which are loaded at address `origin`, into a listing. An
+// instruction cut short by the end of `bytes` is listed as data rather than
+// with invented operands.
+pub fn disassemble(bytes: &[u8], origin: u16) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let (instruction, length) = decode(&bytes[offset..]);
+        let end = (offset + length).min(bytes.len());
+
+        lines.push(Line {
+            addr: origin.wrapping_add(offset as u16),
+            bytes: bytes[offset..end].to_vec(),
+            instruction: Some(instruction).filter(|_| end - offset == length)
+        });
+        offset += length;
+    }
+
+    lines
+}
+
+// Disassemble the memory between `start` and `end` (exclusive) as seen
+// through `bus`, e.g. `disassemble_range(&mut machine.memory, 0x100, 0x200)`.
+pub fn disassemble_range<B: Bus>(bus: &mut B, start: u16, end: u16) -> Result<Vec<Line>, Error> {
+    let mut bytes = Vec::new();
+    let mut addr = start;
+
+    while addr != end {
+        bytes.push(bus.read(addr)?);
+        addr = addr.wrapping_add(1);
+    }
+
+    Ok(disassemble(&bytes, start))
+}
+
+// Render `bytes` as an Intel-syntax listing, one instruction per line.
+pub fn listing(bytes: &[u8], origin: u16) -> String {
+    disassemble(bytes, origin)
+        .iter()
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let raw: Vec<String> = self.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        write!(f, "{:04X}  {:<10}", self.addr, raw.join(" "))?;
+
+        let data: Vec<String> = self.bytes.iter().map(|&b| byte(b)).collect();
+        match self.instruction {
+            Some(instruction) if is_undocumented(self.bytes[0]) => {
+                write!(f, "DB {}  ; {} (undocumented)", data.join(","), instruction)
+            }
+            Some(instruction) => write!(f, "{}", instruction),
+            None => write!(f, "DB {}", data.join(","))
+        }
+    }
+}
+
+// Intel hex constants carry an H suffix and must start with a digit.
+fn byte(value: u8) -> String {
+    let digits = format!("{:02X}H", value);
+    if digits.starts_with(|c: char| c.is_ascii_alphabetic()) { format!("0{}", digits) } else { digits }
+}
+
+fn word(value: u16) -> String {
+    let digits = format!("{:04X}H", value);
+    if digits.starts_with(|c: char| c.is_ascii_alphabetic()) { format!("0{}", digits) } else { digits }
+}
+
+fn register(reg: Reg8) -> &'static str {
+    match reg {
+        Reg8::B => "B",
+        Reg8::C => "C",
+        Reg8::D => "D",
+        Reg8::E => "E",
+        Reg8::H => "H",
+        Reg8::L => "L",
+        Reg8::M => "M",
+        Reg8::A => "A"
+    }
+}
+
+// Intel syntax names a pair after its high register.
+fn pair(pair: RegPair) -> &'static str {
+    match pair {
+        RegPair::BC => "B",
+        RegPair::DE => "D",
+        RegPair::HL => "H",
+        RegPair::SP => "SP",
+        RegPair::PSW => "PSW"
+    }
+}
+
+fn condition(condition: Condition) -> &'static str {
+    match condition {
+        Condition::NotZero => "NZ",
+        Condition::Zero => "Z",
+        Condition::NoCarry => "NC",
+        Condition::Carry => "C",
+        Condition::ParityOdd => "PO",
+        Condition::ParityEven => "PE",
+        Condition::Plus => "P",
+        Condition::Minus => "M"
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::Nop => write!(f, "NOP"),
+            Instruction::Lxi(rp, data) => write!(f, "LXI {},{}", pair(rp), word(data)),
+            Instruction::Stax(rp) => write!(f, "STAX {}", pair(rp)),
+            Instruction::Ldax(rp) => write!(f, "LDAX {}", pair(rp)),
+            Instruction::Shld(addr) => write!(f, "SHLD {}", word(addr)),
+            Instruction::Lhld(addr) => write!(f, "LHLD {}", word(addr)),
+            Instruction::Sta(addr) => write!(f, "STA {}", word(addr)),
+            Instruction::Lda(addr) => write!(f, "LDA {}", word(addr)),
+            Instruction::Inx(rp) => write!(f, "INX {}", pair(rp)),
+            Instruction::Dcx(rp) => write!(f, "DCX {}", pair(rp)),
+            Instruction::Inr(r) => write!(f, "INR {}", register(r)),
+            Instruction::Dcr(r) => write!(f, "DCR {}", register(r)),
+            Instruction::Mvi(r, data) => write!(f, "MVI {},{}", register(r), byte(data)),
+            Instruction::Dad(rp) => write!(f, "DAD {}", pair(rp)),
+            Instruction::Rlc => write!(f, "RLC"),
+            Instruction::Rrc => write!(f, "RRC"),
+            Instruction::Ral => write!(f, "RAL"),
+            Instruction::Rar => write!(f, "RAR"),
+            Instruction::Daa => write!(f, "DAA"),
+            Instruction::Cma => write!(f, "CMA"),
+            Instruction::Stc => write!(f, "STC"),
+            Instruction::Cmc => write!(f, "CMC"),
+            Instruction::Mov(dst, src) => write!(f, "MOV {},{}", register(dst), register(src)),
+            Instruction::Hlt => write!(f, "HLT"),
+            Instruction::Add(r) => write!(f, "ADD {}", register(r)),
+            Instruction::Adc(r) => write!(f, "ADC {}", register(r)),
+            Instruction::Sub(r) => write!(f, "SUB {}", register(r)),
+            Instruction::Sbb(r) => write!(f, "SBB {}", register(r)),
+            Instruction::Ana(r) => write!(f, "ANA {}", register(r)),
+            Instruction::Xra(r) => write!(f, "XRA {}", register(r)),
+            Instruction::Ora(r) => write!(f, "ORA {}", register(r)),
+            Instruction::Cmp(r) => write!(f, "CMP {}", register(r)),
+            Instruction::Adi(data) => write!(f, "ADI {}", byte(data)),
+            Instruction::Aci(data) => write!(f, "ACI {}", byte(data)),
+            Instruction::Sui(data) => write!(f, "SUI {}", byte(data)),
+            Instruction::Sbi(data) => write!(f, "SBI {}", byte(data)),
+            Instruction::Ani(data) => write!(f, "ANI {}", byte(data)),
+            Instruction::Xri(data) => write!(f, "XRI {}", byte(data)),
+            Instruction::Ori(data) => write!(f, "ORI {}", byte(data)),
+            Instruction::Cpi(data) => write!(f, "CPI {}", byte(data)),
+            Instruction::Jmp(addr) => write!(f, "JMP {}", word(addr)),
+            Instruction::Jcc(cc, addr) => write!(f, "J{} {}", condition(cc), word(addr)),
+            Instruction::Call(addr) => write!(f, "CALL {}", word(addr)),
+            Instruction::Ccc(cc, addr) => write!(f, "C{} {}", condition(cc), word(addr)),
+            Instruction::Ret => write!(f, "RET"),
+            Instruction::Rcc(cc) => write!(f, "R{}", condition(cc)),
+            Instruction::Rst(n) => write!(f, "RST {}", n),
+            Instruction::Pchl => write!(f, "PCHL"),
+            Instruction::Push(rp) => write!(f, "PUSH {}", pair(rp)),
+            Instruction::Pop(rp) => write!(f, "POP {}", pair(rp)),
+            Instruction::Xthl => write!(f, "XTHL"),
+            Instruction::Sphl => write!(f, "SPHL"),
+            Instruction::Xchg => write!(f, "XCHG"),
+            Instruction::In(port) => write!(f, "IN {}", byte(port)),
+            Instruction::Out(port) => write!(f, "OUT {}", byte(port)),
+            Instruction::Ei => write!(f, "EI"),
+            Instruction::Di => write!(f, "DI")
+        }
+    }
+}
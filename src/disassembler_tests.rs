@@ -0,0 +1,71 @@
+use crate::cpu::decoder::Instruction;
+use crate::disassembler::{disassemble, disassemble_range, listing, Line};
+
+#[test]
+fn intel_mnemonics() {
+    let program = [
+        0x3e, 0x3e, // MVI A,3EH
+        0x31, 0x00, 0x24, // LXI SP,2400H
+        0xc2, 0x00, 0x01, // JNZ 0100H
+        0xcd, 0xa0, 0xc3, // CALL 0C3A0H
+        0xfe, 0xff, // CPI 0FFH
+        0xf5, // PUSH PSW
+        0x7e, // MOV A,M
+        0xe8, // RPE
+        0xd7, // RST 2
+        0xd3, 0x10 // OUT 10H
+    ];
+
+    let text: Vec<String> = disassemble(&program, 0)
+        .iter()
+        .map(|line| line.instruction.unwrap().to_string())
+        .collect();
+
+    assert_eq!(text, vec![
+        "MVI A,3EH", "LXI SP,2400H", "JNZ 0100H", "CALL 0C3A0H", "CPI 0FFH",
+        "PUSH PSW", "MOV A,M", "RPE", "RST 2", "OUT 10H"
+    ]);
+}
+
+#[test]
+fn listing_shows_addresses_and_bytes() {
+    let program = [0x21, 0x34, 0x12, 0x76, 0xc3, 0x34];
+
+    // The JMP is cut short, so its bytes are listed as data.
+    assert_eq!(listing(&program, 0x0100), "\
+0100  21 34 12  LXI H,1234H
+0103  76        HLT
+0104  C3 34     DB 0C3H,34H
+");
+
+    let lines = disassemble(&program, 0x0100);
+    assert_eq!(lines[1], Line { addr: 0x0103, bytes: vec![0x76], instruction: Some(Instruction::Hlt) });
+    assert_eq!(lines[2].instruction, None);
+}
+
+#[test]
+fn listing_marks_undocumented_opcodes() {
+    let program = [0x08, 0xcb, 0x00, 0x01, 0xd9, 0x00];
+
+    assert_eq!(listing(&program, 0), "\
+0000  08        DB 08H  ; NOP (undocumented)
+0001  CB 00 01  DB 0CBH,00H,01H  ; JMP 0100H (undocumented)
+0004  D9        DB 0D9H  ; RET (undocumented)
+0005  00        NOP
+");
+
+    // The decoded alias is still there for callers that want it.
+    assert_eq!(disassemble(&program, 0)[1].instruction, Some(Instruction::Jmp(0x0100)));
+}
+
+#[test]
+fn disassemble_machine_memory() {
+    let mut machine = crate::cpu::intel8080::Intel8080::new();
+    machine.memory[0x100] = 0xc3;
+    machine.memory[0x101] = 0x00;
+    machine.memory[0x102] = 0x01;
+
+    let lines = disassemble_range(&mut machine.memory, 0x100, 0x103).unwrap();
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0].to_string(), "0100  C3 00 01  JMP 0100H");
+}
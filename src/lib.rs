@@ -1,3 +1,13 @@
 #[cfg(test)]
 pub mod opcode_tests;
-pub mod cpu;
\ No newline at end of file
+#[cfg(test)]
+pub mod cpu_tests;
+#[cfg(test)]
+pub mod decoder_tests;
+#[cfg(test)]
+pub mod disassembler_tests;
+#[cfg(test)]
+pub mod assembler_tests;
+pub mod assembler;
+pub mod cpu;
+pub mod disassembler;
\ No newline at end of file
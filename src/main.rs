@@ -1,19 +1,60 @@
 use std::env;
+use std::fs;
 use std::process;
 
-use emulator_intel8080::cpu::intel8080::Intel8080;
+use emulator_intel8080::cpu::intel8080::{Intel8080, StopReason};
+use emulator_intel8080::disassembler::listing;
 
 
+fn usage(program: &str) -> ! {
+    println!("Usage: {} [-d|--disassemble] <file> [origin]", program);
+    println!("  -d, --disassemble   print an Intel-syntax listing of <file>, loaded at");
+    println!("                      the hexadecimal [origin] (default 0000), instead of running it");
+    process::exit(1);
+}
+
+fn disassemble(path: &str, origin: Option<&String>) {
+    let origin = match origin.map(|o| u16::from_str_radix(o.trim_end_matches(['h', 'H']), 16)) {
+        None => 0,
+        Some(Ok(origin)) => origin,
+        Some(Err(_)) => {
+            eprintln!("{}: not a hexadecimal address", origin.unwrap());
+            process::exit(1);
+        }
+    };
+
+    match fs::read(path) {
+        Ok(bytes) => print!("{}", listing(&bytes, origin)),
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        }
+    }
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
     if args.len() < 2 {
-        println!("Usage: {} - Executable file not provided.", &args[0]);
-        process::exit(1);
+        usage(&args[0]);
+    }
+
+    if args[1] == "-d" || args[1] == "--disassemble" {
+        if args.len() < 3 {
+            usage(&args[0]);
+        }
+        disassemble(&args[2], args.get(3));
+        return;
     }
 
     let mut machine = Intel8080::new();
     
-    machine.load_program(&args[1]); 
+    if let Err(e) = machine.load_program(&args[1]) {
+        eprintln!("{}: {}", &args[1], e);
+        process::exit(1);
+    }
 
-    machine.run();
+    if let StopReason::Fault(e) = machine.run() {
+        eprintln!("{:04x}: {}", machine.pc, e);
+        process::exit(1);
+    }
 }
@@ -1,4 +1,5 @@
 use crate::cpu::intel8080::Intel8080;
+use crate::cpu::ConditionFlags;
 
 #[test]
 fn emulate_opcodes_1_and_2 () {
@@ -545,7 +546,7 @@ fn emulate_cmp() {
 
     machine.run();
 
-    assert_eq!(machine.regs.a, 0x05);
+    assert_eq!(machine.regs.a, 0x0a);
     assert_eq!(machine.flags.sign, 0);
     assert_eq!(machine.flags.zero, 0);
     assert_eq!(machine.flags.parity, 1);
@@ -584,7 +585,7 @@ fn emulate_rnz() {
 
     machine.run();
 
-    assert_eq!(machine.pc, 0x04);
+    assert_eq!(machine.pc, 0x05);
     assert_eq!(machine.sp, 0x07);
 }
 
@@ -602,7 +603,7 @@ fn emulate_jnz() {
 
     machine.run();
 
-    assert_eq!(machine.pc, 0x04);
+    assert_eq!(machine.pc, 0x05);
 }
 
 #[test]
@@ -620,7 +621,7 @@ fn emulate_cnz() {
 
     machine.run();
 
-    assert_eq!(machine.pc, 0x08);
+    assert_eq!(machine.pc, 0x09);
     assert_eq!(machine.memory[10], 0x00);
     assert_eq!(machine.memory[9], 0x08);
     assert_eq!(machine.sp, 0x09);
@@ -662,6 +663,295 @@ fn emulate_rst() {
 
     assert_eq!(machine.memory[5], 0x00);
     assert_eq!(machine.memory[4], 0x04);
-    assert_eq!(machine.pc, 0x02);
+    assert_eq!(machine.pc, 0x03);
     assert_eq!(machine.sp, 0x04);
-}
\ No newline at end of file
+}
+
+#[test]
+fn emulate_daa_after_add() {
+    let mut machine = Intel8080::new();
+    machine.regs.a = 0x38;
+    machine.regs.b = 0x29;
+
+    machine.memory = vec![
+        0x80, // ADD B
+        0x27, // DAA
+        0x76
+    ];
+
+    machine.step().unwrap();
+
+    assert_eq!(machine.regs.a, 0x61);
+    assert_eq!(machine.flags.aux_carry, 1);
+
+    machine.run();
+
+    assert_eq!(machine.regs.a, 0x67);
+    assert_eq!(machine.flags.aux_carry, 0);
+    assert_eq!(machine.flags.carry, 0);
+}
+
+#[test]
+fn emulate_aux_carry() {
+    let mut machine = Intel8080::new();
+    machine.regs.a = 0x10;
+    machine.regs.b = 0x01;
+    machine.regs.c = 0x0f;
+    machine.regs.d = 0x08;
+
+    machine.memory = vec![
+        0x90, // SUB B: borrow from bit 4 clears AC
+        0x0c, // INR C
+        0x15, // DCR D
+        0xa2, // ANA D: AC is bit 3 of A OR bit 3 of D
+        0xee, 0x00, // XRI 00
+        0x76
+    ];
+
+    machine.step().unwrap();
+    assert_eq!(machine.regs.a, 0x0f);
+    assert_eq!(machine.flags.aux_carry, 0);
+
+    machine.step().unwrap();
+    assert_eq!(machine.regs.c, 0x10);
+    assert_eq!(machine.flags.aux_carry, 1);
+
+    machine.step().unwrap();
+    assert_eq!(machine.regs.d, 0x07);
+    assert_eq!(machine.flags.aux_carry, 1);
+
+    machine.step().unwrap();
+    assert_eq!(machine.regs.a, 0x07);
+    assert_eq!(machine.flags.aux_carry, 1);
+    assert_eq!(machine.flags.carry, 0);
+
+    machine.step().unwrap();
+    assert_eq!(machine.flags.aux_carry, 0);
+}
+
+#[test]
+fn emulate_push_and_pop_psw() {
+    let mut machine = Intel8080::new();
+    machine.regs.a = 0x42;
+    machine.flags.sign = 1;
+    machine.flags.aux_carry = 1;
+    machine.flags.carry = 1;
+    machine.sp = 0x08;
+
+    machine.memory = vec![
+        0xf5, // PUSH PSW
+        0xf1, // POP PSW
+        0x76,
+        0, 0, 0, 0, 0
+    ];
+
+    machine.step().unwrap();
+
+    assert_eq!(machine.memory[7], 0x42);
+    assert_eq!(machine.memory[6], 0x93);
+
+    machine.flags = ConditionFlags::new();
+    machine.memory[6] = 0xff;
+    machine.run();
+
+    // Bits 1, 3 and 5 do not exist in the flags register.
+    assert_eq!(machine.flags.pack(), 0xd7);
+    assert_eq!(machine.regs.a, 0x42);
+    assert_eq!(machine.sp, 0x08);
+}
+
+#[test]
+fn emulate_conditional_branches() {
+    // (flags as a PSW byte, offset of the condition in the Jcc/Ccc/Rcc
+    // opcodes, whether the branch is taken)
+    let conditions = [
+        (0x02, 0x00, true), // NZ
+        (0x02, 0x08, false), // Z
+        (0x03, 0x10, false), // NC
+        (0x03, 0x18, true), // C
+        (0x06, 0x20, false), // PO
+        (0x06, 0x28, true), // PE
+        (0x82, 0x30, false), // P
+        (0x82, 0x38, true) // M
+    ];
+
+    for &(psw, offset, taken) in &conditions {
+        // Jcc 0010
+        let mut machine = Intel8080::new();
+        machine.flags = ConditionFlags::unpack(psw);
+        machine.memory[0] = 0xc2 + offset;
+        machine.memory[1] = 0x10;
+        machine.step().unwrap();
+        assert_eq!(machine.pc, if taken { 0x10 } else { 0x03 }, "jump {:02x}", offset);
+
+        // Ccc 1234 pushes the return address high byte first.
+        let mut machine = Intel8080::new();
+        machine.flags = ConditionFlags::unpack(psw);
+        machine.sp = 0x100;
+        machine.pc = 0x200;
+        machine.memory[0x200] = 0xc4 + offset;
+        machine.memory[0x201] = 0x34;
+        machine.memory[0x202] = 0x12;
+        machine.step().unwrap();
+        if taken {
+            assert_eq!(machine.pc, 0x1234, "call {:02x}", offset);
+            assert_eq!(machine.sp, 0xfe);
+            assert_eq!(machine.memory[0xff], 0x02);
+            assert_eq!(machine.memory[0xfe], 0x03);
+        } else {
+            assert_eq!(machine.pc, 0x203, "call {:02x}", offset);
+            assert_eq!(machine.sp, 0x100);
+        }
+
+        // Rcc returns to the address that Ccc pushed.
+        let mut machine = Intel8080::new();
+        machine.flags = ConditionFlags::unpack(psw);
+        machine.sp = 0xfe;
+        machine.memory[0xfe] = 0x03;
+        machine.memory[0xff] = 0x02;
+        machine.memory[0] = 0xc0 + offset;
+        machine.step().unwrap();
+        if taken {
+            assert_eq!(machine.pc, 0x203, "return {:02x}", offset);
+            assert_eq!(machine.sp, 0x100);
+        } else {
+            assert_eq!(machine.pc, 0x01, "return {:02x}", offset);
+            assert_eq!(machine.sp, 0xfe);
+        }
+    }
+}
+
+#[test]
+fn emulate_xchg() {
+    let mut machine = Intel8080::new();
+    machine.regs.d = 0x33;
+    machine.regs.e = 0x55;
+    machine.regs.h = 0x00;
+    machine.regs.l = 0xff;
+
+    machine.memory = vec![
+        0xeb,
+        0x76
+    ];
+
+    machine.run();
+
+    assert_eq!(machine.regs.d, 0x00);
+    assert_eq!(machine.regs.e, 0xff);
+    assert_eq!(machine.regs.h, 0x33);
+    assert_eq!(machine.regs.l, 0x55);
+}
+
+#[test]
+fn emulate_xthl() {
+    let mut machine = Intel8080::new();
+    machine.sp = 0x03;
+    machine.regs.h = 0x0b;
+    machine.regs.l = 0x3c;
+
+    machine.memory = vec![
+        0xe3,
+        0x76,
+        0, 0xf0, 0x0d
+    ];
+
+    machine.run();
+
+    assert_eq!(machine.regs.h, 0x0d);
+    assert_eq!(machine.regs.l, 0xf0);
+    assert_eq!(machine.memory[3], 0x3c);
+    assert_eq!(machine.memory[4], 0x0b);
+    assert_eq!(machine.sp, 0x03);
+}
+
+#[test]
+fn emulate_immediates_match_register_forms() {
+    // Each immediate opcode next to its register form with B as operand.
+    let pairs = [
+        (0xc6, 0x80), (0xce, 0x88), (0xd6, 0x90), (0xde, 0x98),
+        (0xe6, 0xa0), (0xee, 0xa8), (0xf6, 0xb0), (0xfe, 0xb8)
+    ];
+    let values = [0x00, 0x01, 0x0f, 0x10, 0x7f, 0x80, 0x99, 0xf0, 0xff];
+
+    for &(immediate, register) in &pairs {
+        for &a in &values {
+            for &data in &values {
+                for &carry in &[0, 1] {
+                    let mut by_data = Intel8080::new();
+                    by_data.regs.a = a;
+                    by_data.flags.carry = carry;
+                    by_data.memory[0] = immediate;
+                    by_data.memory[1] = data;
+                    by_data.step().unwrap();
+
+                    let mut by_register = Intel8080::new();
+                    by_register.regs.a = a;
+                    by_register.regs.b = data;
+                    by_register.flags.carry = carry;
+                    by_register.memory[0] = register;
+                    by_register.step().unwrap();
+
+                    let case = format!("{:02x} a={:02x} data={:02x} cy={}", immediate, a, data, carry);
+                    assert_eq!(by_data.regs.a, by_register.regs.a, "{}", case);
+                    assert_eq!(by_data.flags.pack(), by_register.flags.pack(), "{}", case);
+                    assert_eq!(by_data.pc, 2, "{}", case);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn emulate_immediate_flags() {
+    let mut machine = Intel8080::new();
+    machine.regs.a = 0x00;
+    machine.flags.carry = 1;
+
+    machine.memory = vec![
+        0xde, 0x01, // SBI 01: 0x00 - 0x01 - 1 borrows
+        0xe6, 0xf0, // ANI F0 clears carry
+        0xfe, 0xe0, // CPI E0 leaves A alone
+        0x76
+    ];
+
+    machine.step().unwrap();
+    assert_eq!(machine.regs.a, 0xfe);
+    assert_eq!(machine.flags.carry, 1);
+    assert_eq!(machine.flags.sign, 1);
+    assert_eq!(machine.flags.aux_carry, 0);
+
+    machine.step().unwrap();
+    assert_eq!(machine.regs.a, 0xf0);
+    assert_eq!(machine.flags.carry, 0);
+    assert_eq!(machine.flags.aux_carry, 1);
+
+    machine.run();
+    assert_eq!(machine.regs.a, 0xf0);
+    assert_eq!(machine.flags.zero, 0);
+    assert_eq!(machine.flags.carry, 0);
+    assert_eq!(machine.flags.aux_carry, 1);
+    assert_eq!(machine.flags.sign, 0);
+}
+
+// The carry out of an addition is not part of the result its parity is
+// taken from.
+#[test]
+fn emulate_add_parity_ignores_carry_out() {
+    let mut machine = Intel8080::new();
+    machine.regs.a = 0x80;
+    machine.regs.b = 0x80;
+    machine.memory[..5].copy_from_slice(&[
+        0x80, // ADD B: 80 + 80 = 00, carry
+        0x3e, 0xff, // MVI A,FF
+        0xce, 0x01 // ACI 01: FF + 01 + 1 = 01, carry
+    ]);
+
+    machine.step().unwrap();
+    assert_eq!(machine.regs.a, 0x00);
+    assert_eq!((machine.flags.carry, machine.flags.parity), (1, 1));
+
+    machine.step().unwrap();
+    machine.step().unwrap();
+    assert_eq!(machine.regs.a, 0x01);
+    assert_eq!((machine.flags.carry, machine.flags.parity), (1, 0));
+}